@@ -1,3 +1,11 @@
+use aho_corasick::AhoCorasick;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 
 // Test input - part 1
@@ -18,77 +26,316 @@ zoneight234
 
 const INPUT: &str = include_str!("../../inputs/day-01.txt");
 
-fn main() {
+const ENGLISH: [(&str, u32); 9] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+];
+
+const FRENCH: [(&str, u32); 9] = [
+    ("un", 1),
+    ("deux", 2),
+    ("trois", 3),
+    ("quatre", 4),
+    ("cinq", 5),
+    ("six", 6),
+    ("sept", 7),
+    ("huit", 8),
+    ("neuf", 9),
+];
+
+const SPANISH: [(&str, u32); 9] = [
+    ("uno", 1),
+    ("dos", 2),
+    ("tres", 3),
+    ("cuatro", 4),
+    ("cinco", 5),
+    ("seis", 6),
+    ("siete", 7),
+    ("ocho", 8),
+    ("nueve", 9),
+];
+
+fn main() -> Result<()> {
+    // Usage: day-01 [english|french|spanish] [--audit [--numeric] [--csv]] [--input PATH] [--bench N]
+    let (mut language, mut audit, mut numeric, mut csv) =
+        ("english".to_string(), false, false, false);
+    let (mut path, mut bench) = (None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--audit" => audit = true,
+            "--numeric" => numeric = true,
+            "--csv" => csv = true,
+            "--input" => path = Some(args.next().context("Missing path for --input")?),
+            "--bench" => bench = Some(args.next().context("Missing count for --bench")?.parse()?),
+            flag if flag.starts_with("--") => bail!("Unknown option: {flag}"),
+            _ => language = arg,
+        }
+    }
+
+    let dictionary = if numeric {
+        Dictionary::numeric()?
+    } else {
+        Dictionary::from_language(&language)?
+    };
+
+    if audit {
+        let input = match &path {
+            Some(path) => {
+                std::fs::read_to_string(path).with_context(|| format!("Cannot read {path}"))?
+            }
+            None => INPUT.to_string(),
+        };
+        let audits = audit_lines(&input, &dictionary);
+        if csv {
+            print!("{}", audits_to_csv(&audits));
+        } else {
+            print!("{}", audits_to_table(&audits));
+        }
+        return Ok(());
+    }
+
+    if let Some(repeats) = bench {
+        return benchmark(&INPUT.repeat(repeats), &dictionary);
+    }
+
     println!("Day 01");
-    println!("\t1: {}", part_1(INPUT));
-    println!("\t2: {}", part_2(INPUT));
+    if let Some(path) = path {
+        let open = || File::open(&path).map(BufReader::new);
+        println!(
+            "\t1: {}",
+            sum_calibration(open()?, &Dictionary::numeric()?)?
+        );
+        println!("\t2: {}", sum_calibration(open()?, &dictionary)?);
+    } else {
+        println!("\t1: {}", part_1(INPUT)?);
+        println!("\t2: {}", part_2(INPUT, &dictionary)?);
+    }
+
+    Ok(())
 }
 
-fn parse_spelled_digits(line: &str, i: usize, digits: &mut Vec<char>) {
-    if i < line.len() {
-        if i >= 2 {
-            if let Some(digit) = match &line[i - 2..=i] {
-                "one" => Some('1'),
-                "two" => Some('2'),
-                "six" => Some('6'),
-                _ => None,
-            } {
-                digits.push(digit)
-            }
+// Matches spelled out digits from an arbitrary word -> digit dictionary.
+// Matches are reported overlapping so that "eightwo" yields both 8 and 2.
+struct Dictionary {
+    matcher: AhoCorasick,
+    words: Vec<(String, u32)>,
+}
+
+impl Dictionary {
+    fn new<'a>(words: impl IntoIterator<Item = (&'a str, u32)>) -> Result<Self> {
+        let words = words
+            .into_iter()
+            .map(|(word, digit)| (word.to_string(), digit))
+            .collect_vec();
+        if let Some((_, d)) = words.iter().find(|(_, d)| *d > 9) {
+            bail!("Spelled digit must be a single digit, got {d}")
         }
-        if i >= 3 {
-            if let Some(digit) = match &line[i - 3..=i] {
-                "four" => Some('4'),
-                "five" => Some('5'),
-                "nine" => Some('9'),
-                _ => None,
-            } {
-                digits.push(digit)
-            }
+
+        Ok(Self {
+            matcher: AhoCorasick::new(words.iter().map(|(word, _)| word))?,
+            words,
+        })
+    }
+
+    // Only matches numeric digits
+    fn numeric() -> Result<Self> {
+        Self::new([])
+    }
+
+    fn from_language(language: &str) -> Result<Self> {
+        match language.to_lowercase().as_str() {
+            "en" | "english" => Self::new(ENGLISH),
+            "fr" | "french" => Self::new(FRENCH),
+            "es" | "spanish" => Self::new(SPANISH),
+            l => bail!("Unknown language: {l}"),
         }
-        if i >= 4 {
-            if let Some(digit) = match &line[i - 4..=i] {
-                "three" => Some('3'),
-                "seven" => Some('7'),
-                "eight" => Some('8'),
-                _ => None,
-            } {
-                digits.push(digit)
-            }
+    }
+
+    // Returns every digit (numeric or spelled) in the line along with its starting byte offset
+    fn find_digits(&self, line: &str) -> Vec<(usize, u32)> {
+        let numeric = line
+            .char_indices()
+            .flat_map(|(i, c)| c.to_digit(10).map(|d| (i, d)));
+        let spelled = self
+            .matcher
+            .find_overlapping_iter(line)
+            .map(|m| (m.start(), self.words[m.pattern().as_usize()].1));
+
+        numeric.chain(spelled).sorted().collect_vec()
+    }
+
+    // Digit starting at byte `i` of the line if there is one
+    fn digit_at(&self, line: &[u8], i: usize) -> Option<u32> {
+        if line[i].is_ascii_digit() {
+            return Some((line[i] - b'0') as u32);
         }
+        self.words
+            .iter()
+            .find(|(word, _)| line[i..].starts_with(word.as_bytes()))
+            .map(|(_, d)| *d)
+    }
+
+    // Allocation free: scans from the start for the first digit and from the end for the last one
+    fn calibration_value(&self, line: &[u8]) -> Option<usize> {
+        let first = (0..line.len()).find_map(|i| self.digit_at(line, i))?;
+        let last = (0..line.len()).rev().find_map(|i| self.digit_at(line, i))?;
+
+        Some((first * 10 + last) as usize)
     }
 }
 
-fn part_1(input: &str) -> usize {
-    input
-        .split_whitespace()
-        .flat_map(|line| {
-            let digits = line.chars().filter(|c| c.is_ascii_digit()).collect_vec();
-            format!("{}{}", digits[0], digits[digits.len() - 1]).parse::<usize>()
-        })
-        .sum::<usize>()
+// Calibration audit: digits detected on a single line and the value they yield
+struct LineAudit<'a> {
+    number: usize,
+    line: &'a str,
+    digits: Vec<(usize, u32)>,
 }
 
-fn part_2(input: &str) -> usize {
+impl LineAudit<'_> {
+    fn first(&self) -> Option<u32> {
+        self.digits.first().map(|(_, d)| *d)
+    }
+
+    fn last(&self) -> Option<u32> {
+        self.digits.last().map(|(_, d)| *d)
+    }
+
+    fn value(&self) -> Result<usize> {
+        match (self.first(), self.last()) {
+            (Some(first), Some(last)) => Ok((first * 10 + last) as usize),
+            _ => bail!("No digits on line {}: '{}'", self.number, self.line),
+        }
+    }
+
+    fn digits_repr(&self) -> String {
+        self.digits
+            .iter()
+            .map(|(i, d)| format!("{d}@{i}"))
+            .join(" ")
+    }
+}
+
+fn audit_lines<'a>(input: &'a str, dictionary: &Dictionary) -> Vec<LineAudit<'a>> {
     input
-        .split_whitespace()
-        .flat_map(|line| {
-            let digits = line
-                .char_indices()
-                .flat_map(|(i, c)| {
-                    let mut d = vec![];
-
-                    if c.is_ascii_digit() {
-                        d.push(c)
-                    }
-
-                    parse_spelled_digits(line, i, &mut d);
-
-                    d
-                })
-                .collect_vec();
-            format!("{}{}", digits[0], digits[digits.len() - 1]).parse::<usize>()
+        .lines()
+        .enumerate()
+        .map(|(i, line)| LineAudit {
+            number: i + 1,
+            line,
+            digits: dictionary.find_digits(line),
         })
+        .collect_vec()
+}
+
+fn audits_to_table(audits: &[LineAudit]) -> String {
+    let width = audits
+        .iter()
+        .map(|a| a.digits_repr().len())
+        .chain([6])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:>5} | {:<width$} | first | last | value\n",
+        "line", "digits"
+    );
+    for audit in audits {
+        let fmt = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or("-".into());
+        let value = match audit.value() {
+            Ok(v) => v.to_string(),
+            Err(e) => format!("ERROR: {e}"),
+        };
+        table.push_str(&format!(
+            "{:>5} | {:<width$} | {:>5} | {:>4} | {value}\n",
+            audit.number,
+            audit.digits_repr(),
+            fmt(audit.first()),
+            fmt(audit.last()),
+        ));
+    }
+
+    table
+}
+
+fn audits_to_csv(audits: &[LineAudit]) -> String {
+    let mut csv = String::from("line,digits,first,last,value,error\n");
+    for audit in audits {
+        let fmt = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or_default();
+        let (value, error) = match audit.value() {
+            Ok(v) => (v.to_string(), String::new()),
+            Err(e) => (
+                String::new(),
+                format!("\"{}\"", e.to_string().replace('"', "\"\"")),
+            ),
+        };
+        csv.push_str(&format!(
+            "{},{},{},{},{value},{error}\n",
+            audit.number,
+            audit.digits_repr(),
+            fmt(audit.first()),
+            fmt(audit.last()),
+        ));
+    }
+
+    csv
+}
+
+// Streams calibration lines from a reader, reusing a single line buffer
+fn sum_calibration(mut reader: impl BufRead, dictionary: &Dictionary) -> Result<usize> {
+    let (mut buf, mut sum, mut number) = (vec![], 0, 0);
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(sum);
+        }
+        number += 1;
+
+        let line = buf.trim_ascii();
+        if line.is_empty() {
+            continue;
+        }
+        sum += dictionary
+            .calibration_value(line)
+            .with_context(|| format!("No digits on line {number}"))?;
+    }
+}
+
+fn benchmark(input: &str, dictionary: &Dictionary) -> Result<()> {
+    println!("Benchmarking on {} bytes", input.len());
+
+    let start = Instant::now();
+    let expected = part_2(input, dictionary)?;
+    println!("\tpart_2:          {expected} in {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let streamed = sum_calibration(input.as_bytes(), dictionary)?;
+    println!("\tsum_calibration: {streamed} in {:?}", start.elapsed());
+
+    if expected != streamed {
+        bail!("Calibration mismatch: {expected} != {streamed}")
+    }
+
+    Ok(())
+}
+
+fn part_1(input: &str) -> Result<usize> {
+    part_2(input, &Dictionary::numeric()?)
+}
+
+// Blank lines are skipped, any other line without digits is an error
+fn part_2(input: &str, dictionary: &Dictionary) -> Result<usize> {
+    audit_lines(input, dictionary)
+        .iter()
+        .filter(|audit| !audit.line.trim().is_empty())
+        .map(LineAudit::value)
         .sum()
 }
 
@@ -98,21 +345,119 @@ mod tests {
 
     #[test]
     fn test_1() {
-        assert_eq!(142, part_1(TEST_1))
+        assert_eq!(142, part_1(TEST_1).unwrap())
     }
 
     #[test]
     fn test_input_1() {
-        assert_eq!(54338, part_1(INPUT))
+        assert_eq!(54338, part_1(INPUT).unwrap())
     }
 
     #[test]
     fn test_2() {
-        assert_eq!(281, part_2(TEST_2))
+        let dictionary = Dictionary::new(ENGLISH).unwrap();
+        assert_eq!(281, part_2(TEST_2, &dictionary).unwrap())
     }
 
     #[test]
     fn test_input_2() {
-        assert_eq!(53389, part_2(INPUT))
+        let dictionary = Dictionary::new(ENGLISH).unwrap();
+        assert_eq!(53389, part_2(INPUT, &dictionary).unwrap())
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let dictionary = Dictionary::new(ENGLISH).unwrap();
+        let cases = [
+            ("eightwo", vec![(0, 8), (4, 2)]),
+            ("oneight", vec![(0, 1), (2, 8)]),
+            ("twone3", vec![(0, 2), (2, 1), (5, 3)]),
+            ("sevenine", vec![(0, 7), (4, 9)]),
+        ];
+
+        for (line, wanted) in cases {
+            assert_eq!(wanted, dictionary.find_digits(line))
+        }
+    }
+
+    #[test]
+    fn test_languages() {
+        let cases = [
+            (
+                "french",
+                "deuxtroisept4",
+                vec![(0, 2), (4, 3), (8, 7), (12, 4)],
+            ),
+            ("spanish", "1dosiete", vec![(0, 1), (1, 2), (3, 7)]),
+        ];
+
+        for (language, line, wanted) in cases {
+            let dictionary = Dictionary::from_language(language).unwrap();
+            assert_eq!(wanted, dictionary.find_digits(line))
+        }
+
+        assert!(Dictionary::from_language("klingon").is_err());
+        assert!(Dictionary::new([("ten", 10)]).is_err());
+    }
+
+    #[test]
+    fn test_streaming() {
+        let numeric = Dictionary::numeric().unwrap();
+        let english = Dictionary::from_language("english").unwrap();
+
+        assert_eq!(
+            part_1(TEST_1).unwrap(),
+            sum_calibration(TEST_1.as_bytes(), &numeric).unwrap()
+        );
+        assert_eq!(
+            part_1(INPUT).unwrap(),
+            sum_calibration(INPUT.as_bytes(), &numeric).unwrap()
+        );
+        assert_eq!(
+            part_2(TEST_2, &english).unwrap(),
+            sum_calibration(TEST_2.as_bytes(), &english).unwrap()
+        );
+        assert_eq!(
+            part_2(INPUT, &english).unwrap(),
+            sum_calibration(INPUT.as_bytes(), &english).unwrap()
+        );
+
+        assert_eq!(Some(82), english.calibration_value(b"eightwo"));
+        assert_eq!(
+            89,
+            sum_calibration("3four\r\n\r\nfive\r\n".as_bytes(), &english).unwrap()
+        );
+        assert!(sum_calibration("1\nabc\n".as_bytes(), &english).is_err());
+    }
+
+    #[test]
+    fn test_audit() {
+        let dictionary = Dictionary::from_language("english").unwrap();
+        let audits = audit_lines("xtwone3four\nabc\n7pqrstsixteen", &dictionary);
+
+        assert_eq!(vec![(1, 2), (3, 1), (6, 3), (7, 4)], audits[0].digits);
+        assert_eq!(24, audits[0].value().unwrap());
+        assert!(audits[1].value().is_err());
+        assert_eq!(76, audits[2].value().unwrap());
+
+        let csv = audits_to_csv(&audits);
+        let rows = csv.lines().collect_vec();
+        assert_eq!("1,2@1 1@3 3@6 4@7,2,4,24,", rows[1]);
+        assert_eq!("2,,,,,\"No digits on line 2: 'abc'\"", rows[2]);
+
+        let numeric = Dictionary::numeric().unwrap();
+        let audits = audit_lines(TEST_1, &numeric);
+        let total: usize = audits.iter().flat_map(|a| a.value()).sum();
+        assert_eq!(part_1(TEST_1).unwrap(), total);
+    }
+
+    #[test]
+    fn test_missing_digits() {
+        let english = Dictionary::from_language("english").unwrap();
+        assert_eq!(29 + 77, part_2("two1nine\n\nseven", &english).unwrap());
+
+        let error = part_2("two1nine\nabc\nseven", &english).unwrap_err();
+        assert_eq!("No digits on line 2: 'abc'", error.to_string());
+        assert!(part_1("1abc2\npqrstu").is_err());
     }
 }
@@ -1,13 +1,16 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools;
 use nom::{
-    branch::alt,
     bytes::complete::tag,
-    character::complete::digit1,
+    character::complete::{alpha1, digit1},
     combinator::map_res,
     multi::separated_list1,
     sequence::{preceded, separated_pair},
     IResult,
 };
+use serde::{Deserialize, Serialize};
 
 // Test input - Part 1
 #[allow(dead_code)]
@@ -20,61 +23,291 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
 #[allow(dead_code)]
 const INPUT: &str = include_str!("../../inputs/day-02.txt");
 
-const MAX_R: usize = 12;
-const MAX_G: usize = 13;
-const MAX_B: usize = 14;
+// Default bag: 12 red cubes, 13 green cubes, and 14 blue cubes
+const BAG: &str = "12 red, 13 green, 14 blue";
+
+fn main() -> Result<()> {
+    // Usage:
+    //   day-02 [BAG]                 e.g. day-02 "12 red, 13 green, 14 blue, 3 yellow"
+    //   day-02 impossible BAG        games the bag cannot produce, with the offending draw
+    //   day-02 minimal               smallest bag making every game possible
+    //   day-02 rank BAG [BAG ...]    bags ordered by the number of games they admit
+    //   day-02 report [BAG]          colour distributions and games sorted by power
+    //   day-02 export json|csv       parsed games in an analysis friendly format
+    //   day-02 import FILE [BAG]     solve games previously exported as JSON
+    let mut args = std::env::args().skip(1).collect_vec();
 
-fn main() {
-    let games = INPUT
+    let mut games = INPUT
         .split('\n')
         .flat_map(parse_game)
         .map(|(_, game)| game)
         .collect_vec();
 
+    match args.first().map(String::as_str) {
+        Some("impossible") => {
+            let bag = parse_bag(args.get(1).context("Missing bag")?)?;
+            for (id, i, draw) in impossible_games(&games, &bag) {
+                println!("Game {id}: draw {} ({draw}) exceeds bag ({bag})", i + 1);
+            }
+            return Ok(());
+        }
+        Some("minimal") => {
+            println!("{}", minimal_bag(&games));
+            return Ok(());
+        }
+        Some("rank") => {
+            let bags: Vec<_> = args[1..].iter().map(|b| parse_bag(b)).try_collect()?;
+            for (admitted, bag) in rank_bags(&games, &bags) {
+                println!("{admitted:>5} games: {bag}");
+            }
+            return Ok(());
+        }
+        Some("report") => {
+            let bag = parse_bag(args.get(1).map(String::as_str).unwrap_or(BAG))?;
+            print!("{}", Report::new(&games, &bag));
+            return Ok(());
+        }
+        Some("export") => {
+            match args.get(1).map(String::as_str) {
+                Some("json") => println!("{}", games_to_json(&games)?),
+                Some("csv") => print!("{}", games_to_csv(&games)),
+                f => bail!("Unknown export format: {f:?}"),
+            }
+            return Ok(());
+        }
+        Some("import") => {
+            let path = args.get(1).context("Missing file to import")?;
+            games = games_from_json(&std::fs::read_to_string(path)?)?;
+            args.drain(..2);
+        }
+        _ => {}
+    }
+
+    let bag = parse_bag(args.first().map(String::as_str).unwrap_or(BAG))?;
+
     println!("Day 02");
-    println!("\t1: {}", part_1(&games));
-    println!("\t2: {}", part_2(&games));
+    println!("\t1: {}", part_1(&games, &bag));
+    println!("\t2: {}", part_2(&games, &bag));
+
+    Ok(())
 }
 
-fn part_1(games: &[(usize, Vec<Draw>)]) -> usize {
+fn part_1(games: &[(usize, Vec<Draw>)], bag: &Draw) -> usize {
     games
         .iter()
-        .filter(|(_, draws)| {
+        .filter(|(_, draws)| draws.iter().all(|draw| draw.is_possible(bag)))
+        .fold(0, |acc, (id, _)| acc + id)
+}
+
+fn part_2(games: &[(usize, Vec<Draw>)], bag: &Draw) -> usize {
+    games.iter().map(|(_, draws)| game_power(draws, bag)).sum()
+}
+
+// Power of the smallest bag making a game possible
+fn game_power(draws: &[Draw], bag: &Draw) -> usize {
+    draws
+        .iter()
+        .fold(Draw::default(), |acc, d| acc.max(d))
+        .power(bag)
+}
+
+// QUERIES
+
+// First draw making each impossible game impossible: (game id, draw index, draw)
+fn impossible_games<'a>(
+    games: &'a [(usize, Vec<Draw>)],
+    bag: &Draw,
+) -> Vec<(usize, usize, &'a Draw)> {
+    games
+        .iter()
+        .flat_map(|(id, draws)| {
             draws
                 .iter()
-                .all(|draw| draw.is_possible(MAX_R, MAX_G, MAX_B))
+                .find_position(|draw| !draw.is_possible(bag))
+                .map(|(i, draw)| (*id, i, draw))
         })
-        .fold(0, |acc, (id, _)| acc + id)
+        .collect_vec()
 }
 
-fn part_2(games: &[(usize, Vec<Draw>)]) -> usize {
+fn minimal_bag(games: &[(usize, Vec<Draw>)]) -> Draw {
     games
         .iter()
-        .map(|(_, draws)| {
-            let max = draws.iter().fold(Draw::default(), |acc, d| acc.max(d));
-            max.red * max.green * max.blue
+        .flat_map(|(_, draws)| draws)
+        .fold(Draw::default(), |acc, d| acc.max(d))
+}
+
+// Bags sorted by decreasing number of games they admit
+fn rank_bags<'a>(games: &[(usize, Vec<Draw>)], bags: &'a [Draw]) -> Vec<(usize, &'a Draw)> {
+    bags.iter()
+        .map(|bag| (games.len() - impossible_games(games, bag).len(), bag))
+        .sorted_by(|(a, _), (b, _)| b.cmp(a))
+        .collect_vec()
+}
+
+// REPORTING
+
+#[derive(Debug, Default, PartialEq)]
+struct ColorStats {
+    draws: usize,
+    min: usize,
+    max: usize,
+    total: usize,
+    // cube count -> number of draws with that count
+    histogram: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug)]
+struct Report {
+    colors: BTreeMap<String, ColorStats>,
+    max_draws: usize,
+    mean_draws: f64,
+    // (game id, power) sorted by decreasing power
+    by_power: Vec<(usize, usize)>,
+}
+
+impl Report {
+    fn new(games: &[(usize, Vec<Draw>)], bag: &Draw) -> Self {
+        let mut colors: BTreeMap<String, ColorStats> = BTreeMap::new();
+        for (color, count) in games.iter().flat_map(|(_, draws)| draws).flat_map(|d| &d.0) {
+            let stats = colors.entry(color.clone()).or_insert(ColorStats {
+                min: usize::MAX,
+                ..Default::default()
+            });
+            stats.draws += 1;
+            stats.min = stats.min.min(*count);
+            stats.max = stats.max.max(*count);
+            stats.total += count;
+            *stats.histogram.entry(*count).or_default() += 1;
+        }
+
+        let draws = games.iter().map(|(_, draws)| draws.len()).collect_vec();
+
+        Self {
+            colors,
+            max_draws: draws.iter().max().copied().unwrap_or_default(),
+            mean_draws: draws.iter().sum::<usize>() as f64 / draws.len().max(1) as f64,
+            by_power: games
+                .iter()
+                .map(|(id, draws)| (*id, game_power(draws, bag)))
+                .sorted_by(|(_, a), (_, b)| b.cmp(a))
+                .collect_vec(),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Games: {}", self.by_power.len())?;
+        writeln!(
+            f,
+            "Draws per game: max {}, mean {:.2}",
+            self.max_draws, self.mean_draws
+        )?;
+
+        writeln!(f, "Colours:")?;
+        for (color, stats) in self.colors.iter() {
+            writeln!(
+                f,
+                "\t{color}: {} draws, min {}, max {}, mean {:.2}",
+                stats.draws,
+                stats.min,
+                stats.max,
+                stats.total as f64 / stats.draws as f64
+            )?;
+            let histogram = stats
+                .histogram
+                .iter()
+                .map(|(count, n)| format!("{count}:{n}"))
+                .join(" ");
+            writeln!(f, "\t\t{histogram}")?;
+        }
+
+        writeln!(f, "Games by power:")?;
+        for (id, power) in self.by_power.iter() {
+            writeln!(f, "\tGame {id}: {power}")?;
+        }
+
+        Ok(())
+    }
+}
+
+// EXPORT / IMPORT
+
+#[derive(Serialize, Deserialize)]
+struct GameRecord {
+    id: usize,
+    draws: Vec<Draw>,
+}
+
+fn games_to_json(games: &[(usize, Vec<Draw>)]) -> Result<String> {
+    let records = games
+        .iter()
+        .map(|(id, draws)| GameRecord {
+            id: *id,
+            draws: draws.clone(),
         })
-        .sum()
+        .collect_vec();
+
+    Ok(serde_json::to_string_pretty(&records)?)
+}
+
+fn games_from_json(input: &str) -> Result<Vec<(usize, Vec<Draw>)>> {
+    let records: Vec<GameRecord> = serde_json::from_str(input)?;
+    Ok(records.into_iter().map(|r| (r.id, r.draws)).collect_vec())
 }
 
-#[derive(Default, Debug)]
-struct Draw {
-    red: usize,
-    green: usize,
-    blue: usize,
+// One row per colour in each draw
+fn games_to_csv(games: &[(usize, Vec<Draw>)]) -> String {
+    let mut csv = String::from("game,draw,color,count\n");
+    for (id, draws) in games {
+        for (i, draw) in draws.iter().enumerate() {
+            for (color, count) in draw.0.iter() {
+                csv.push_str(&format!("{id},{},{color},{count}\n", i + 1));
+            }
+        }
+    }
+    csv
 }
 
+// Number of cubes drawn for each colour, colours that were not drawn are absent
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Draw(BTreeMap<String, usize>);
+
 impl Draw {
+    fn count(&self, color: &str) -> usize {
+        self.0.get(color).copied().unwrap_or_default()
+    }
+
     fn max(&self, other: &Self) -> Self {
-        Self {
-            red: self.red.max(other.red),
-            green: self.green.max(other.green),
-            blue: self.blue.max(other.blue),
+        let mut max = self.clone();
+        for (color, count) in other.0.iter() {
+            let entry = max.0.entry(color.clone()).or_default();
+            *entry = (*entry).max(*count);
         }
+        max
+    }
+
+    // A bag is itself a draw holding every cube, so a draw is possible if no colour exceeds it
+    fn is_possible(&self, bag: &Self) -> bool {
+        self.0
+            .iter()
+            .all(|(color, count)| *count <= bag.count(color))
+    }
+
+    // Product of the counts of every colour in the bag, colours never drawn count as 0
+    fn power(&self, bag: &Self) -> usize {
+        bag.0.keys().map(|color| self.count(color)).product()
     }
+}
 
-    fn is_possible(&self, max_red: usize, max_green: usize, max_blue: usize) -> bool {
-        self.red <= max_red && self.green <= max_green && self.blue <= max_blue
+impl Display for Draw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = self
+            .0
+            .iter()
+            .map(|(color, count)| format!("{count} {color}"))
+            .join(", ");
+        write!(f, "{counts}")
     }
 }
 
@@ -84,7 +317,7 @@ fn parse_count(input: &str) -> IResult<&str, usize> {
 }
 
 fn parse_color(input: &str) -> IResult<&str, &str> {
-    alt((tag("red"), tag("green"), tag("blue")))(input)
+    alpha1(input)
 }
 
 fn parse_draw(input: &str) -> IResult<&str, Draw> {
@@ -95,28 +328,22 @@ fn parse_draw(input: &str) -> IResult<&str, Draw> {
 
     Ok((
         remaining,
-        counts.iter().fold(Draw::default(), |acc, (count, color)| {
-            acc.max(
-                &(match *color {
-                    "red" => Draw {
-                        red: *count,
-                        ..Default::default()
-                    },
-                    "green" => Draw {
-                        green: *count,
-                        ..Default::default()
-                    },
-                    "blue" => Draw {
-                        blue: *count,
-                        ..Default::default()
-                    },
-                    _ => unreachable!(),
-                }),
-            )
-        }),
+        counts
+            .into_iter()
+            .fold(Draw::default(), |acc, (count, color)| {
+                acc.max(&Draw(BTreeMap::from([(color.to_string(), count)])))
+            }),
     ))
 }
 
+fn parse_bag(input: &str) -> Result<Draw> {
+    let (remaining, bag) = parse_draw(input.trim()).map_err(|e| anyhow!("Invalid bag: {e}"))?;
+    if !remaining.is_empty() {
+        bail!("Invalid bag, could not parse '{remaining}'")
+    }
+    Ok(bag)
+}
+
 fn parse_game(input: &str) -> IResult<&str, (usize, Vec<Draw>)> {
     separated_pair(
         preceded(tag("Game "), parse_count),
@@ -140,24 +367,119 @@ mod tests {
     #[test]
     fn test_1() {
         let games = parse_games(TEST_1);
-        assert_eq!(8, part_1(&games));
+        let bag = parse_bag(BAG).unwrap();
+        assert_eq!(8, part_1(&games, &bag));
     }
 
     #[test]
     fn test_input_1() {
         let games = parse_games(INPUT);
-        assert_eq!(2545, part_1(&games));
+        let bag = parse_bag(BAG).unwrap();
+        assert_eq!(2545, part_1(&games, &bag));
     }
 
     #[test]
     fn test_2() {
         let games = parse_games(TEST_1);
-        assert_eq!(2286, part_2(&games));
+        assert_eq!(2286, part_2(&games, &parse_bag(BAG).unwrap()));
+
+        // Colours missing from a game count as 0
+        let games = parse_games("Game 1: 3 red, 4 green; 2 red");
+        assert_eq!(0, part_2(&games, &parse_bag(BAG).unwrap()));
     }
 
     #[test]
     fn test_input_2() {
         let games = parse_games(INPUT);
-        assert_eq!(78111, part_2(&games));
+        assert_eq!(78111, part_2(&games, &parse_bag(BAG).unwrap()));
+    }
+
+    #[test]
+    fn test_other_colors() {
+        let games = parse_games(
+            "Game 1: 3 blue, 2 yellow; 1 purple
+Game 2: 5 yellow, 1 blue; 2 purple, 1 red
+Game 3: 1 yellow, 1 red, 1 purple",
+        );
+
+        let bag = parse_bag("3 blue, 2 yellow, 2 purple, 1 red").unwrap();
+        assert_eq!(4, part_1(&games, &bag));
+        // Games 1 and 3 never draw red and blue respectively, so their power is 0
+        assert_eq!(5 * 2, part_2(&games, &bag));
+
+        let bag = parse_bag("3 blue, 5 yellow, 2 purple").unwrap();
+        assert_eq!(1, part_1(&games, &bag));
+        assert!(parse_bag("3 blue, 5").is_err());
+    }
+
+    #[test]
+    fn test_queries() {
+        let games = parse_games(TEST_1);
+        let bag = parse_bag(BAG).unwrap();
+
+        let impossible = impossible_games(&games, &bag)
+            .into_iter()
+            .map(|(id, i, draw)| (id, i, draw.to_string()))
+            .collect_vec();
+        assert_eq!(
+            vec![
+                (3, 0, "6 blue, 8 green, 20 red".to_string()),
+                (4, 2, "15 blue, 3 green, 14 red".to_string())
+            ],
+            impossible
+        );
+
+        let minimal = minimal_bag(&games);
+        assert_eq!(parse_bag("20 red, 13 green, 15 blue").unwrap(), minimal);
+        assert!(impossible_games(&games, &minimal).is_empty());
+
+        let bags = [parse_bag("1 red").unwrap(), minimal.clone(), bag.clone()];
+        let ranked = rank_bags(&games, &bags);
+        assert_eq!(vec![(5, &minimal), (3, &bag), (0, &bags[0])], ranked);
+    }
+
+    #[test]
+    fn test_report() {
+        let games = parse_games(TEST_1);
+        let report = Report::new(&games, &parse_bag(BAG).unwrap());
+
+        assert_eq!(3, report.max_draws);
+        assert!((report.mean_draws - 2.8).abs() < 1e-9);
+        assert_eq!(
+            ColorStats {
+                draws: 11,
+                min: 1,
+                max: 20,
+                total: 4 + 1 + 1 + 20 + 4 + 1 + 3 + 6 + 14 + 6 + 1,
+                histogram: BTreeMap::from([(1, 4), (3, 1), (4, 2), (6, 2), (14, 1), (20, 1)]),
+            },
+            report.colors["red"]
+        );
+        assert_eq!(
+            vec![(3, 1560), (4, 630), (1, 48), (5, 36), (2, 12)],
+            report.by_power
+        );
+    }
+
+    #[test]
+    fn test_export_import() {
+        let games = parse_games(TEST_1);
+
+        let json = games_to_json(&games).unwrap();
+        let imported = games_from_json(&json).unwrap();
+        assert_eq!(games, imported);
+
+        let imported = games_from_json(r#"[{"id": 7, "draws": [{"red": 2, "teal": 1}]}]"#).unwrap();
+        assert_eq!(
+            vec![(7, vec![parse_bag("2 red, 1 teal").unwrap()])],
+            imported
+        );
+        assert!(games_from_json(r#"[{"id": 7, "draws": [{"red": -2}]}]"#).is_err());
+
+        let csv = games_to_csv(&games[..1]);
+        assert_eq!(
+            "game,draw,color,count\n1,1,blue,3\n1,1,red,4\n1,2,blue,6\n1,2,green,2\n1,2,red,1\n1,3,green,2\n",
+            csv
+        );
     }
 }
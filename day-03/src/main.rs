@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 
 const TEST_1: &str = "467..114..
 ...*......
@@ -18,14 +21,99 @@ const TEST_1: &str = "467..114..
 const INPUT: &str = include_str!("../../inputs/day-03.txt");
 
 fn main() -> Result<()> {
-    let (numbers, symbols) = parse_board(INPUT);
+    // Usage: day-03 [--symbols CHARS] [--gear CHAR] [--neighbours N] [--combine product|sum|max]
+    //               [--render ansi|html]
+    let mut rules = Rules::default();
+    let mut render_format = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--symbols" => rules.part_symbols = Some(value()?.chars().collect()),
+            "--gear" => rules.gear_symbol = value()?.chars().next().context("Empty gear symbol")?,
+            "--neighbours" => rules.gear_neighbours = value()?.parse()?,
+            "--combine" => rules.combine = value()?.parse()?,
+            "--render" => render_format = Some(value()?.parse()?),
+            _ => bail!("Unknown option: {arg}"),
+        }
+    }
+
+    let schematic = parse_board(INPUT)?;
+    if let Some(format) = render_format {
+        print!("{}", render(INPUT, &schematic, &rules, format));
+        return Ok(());
+    }
+
     println!("Day 03");
-    println!("\t1: {}", part_1(&numbers, &symbols)?);
-    println!("\t2: {}", part_2(&numbers, &symbols)?);
+    println!("\t1: {}", part_1(&schematic, &rules)?);
+    println!("\t2: {}", part_2(&schematic, &rules)?);
 
     Ok(())
 }
 
+// How gear values are combined from the adjacent numbers
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combine {
+    Product,
+    Sum,
+    Max,
+}
+
+impl FromStr for Combine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "product" => Ok(Self::Product),
+            "sum" => Ok(Self::Sum),
+            "max" => Ok(Self::Max),
+            _ => bail!("Unknown combination: {s}"),
+        }
+    }
+}
+
+impl Combine {
+    fn apply(&self, values: &[usize]) -> usize {
+        match self {
+            Self::Product => values.iter().product(),
+            Self::Sum => values.iter().sum(),
+            Self::Max => values.iter().max().copied().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rules {
+    // Symbols that make an adjacent number a part number, any symbol if None
+    part_symbols: Option<HashSet<char>>,
+    gear_symbol: char,
+    // Exact number of adjacent numbers a gear must have
+    gear_neighbours: usize,
+    combine: Combine,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            part_symbols: None,
+            gear_symbol: '*',
+            gear_neighbours: 2,
+            combine: Combine::Product,
+        }
+    }
+}
+
+impl Rules {
+    fn is_part_symbol(&self, symbol: &char) -> bool {
+        self.part_symbols
+            .as_ref()
+            .is_none_or(|symbols| symbols.contains(symbol))
+    }
+}
+
 fn neighbours(x: usize, y: usize, number_size: usize) -> Vec<(usize, usize)> {
     let mut ys = (y..=y + number_size).map(Some).collect_vec();
     ys.push(y.checked_sub(1));
@@ -38,73 +126,253 @@ fn neighbours(x: usize, y: usize, number_size: usize) -> Vec<(usize, usize)> {
         .collect_vec()
 }
 
-type Numbers = Vec<(String, (usize, usize))>;
+#[derive(Debug, Clone, PartialEq)]
+struct Number {
+    value: usize,
+    // Coordinates of the first digit
+    x: usize,
+    y: usize,
+    len: usize,
+}
+
+impl Number {
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.len).map(|y| (self.x, y))
+    }
+}
+
+type Numbers = Vec<Number>;
 type Symbols = HashMap<(usize, usize), char>;
 
-fn parse_board(input: &str) -> (Numbers, Symbols) {
+// Schematic with an index from every cell covered by a number to that number's id
+#[derive(Debug, Default)]
+struct Schematic {
+    numbers: Numbers,
+    symbols: Symbols,
+    cells: HashMap<(usize, usize), usize>,
+}
+
+impl Schematic {
+    fn new(numbers: Numbers, symbols: Symbols) -> Self {
+        let cells = numbers
+            .iter()
+            .enumerate()
+            .flat_map(|(id, number)| number.cells().map(move |c| (c, id)))
+            .collect();
+
+        Self {
+            numbers,
+            symbols,
+            cells,
+        }
+    }
+
+    // Ids of the numbers touching the cell at (x, y)
+    fn numbers_around(&self, x: usize, y: usize) -> Vec<usize> {
+        neighbours(x, y, 1)
+            .iter()
+            .flat_map(|c| self.cells.get(c).copied())
+            .unique()
+            .collect_vec()
+    }
+
+    // Ids of the numbers touching at least one part symbol
+    fn part_numbers(&self, rules: &Rules) -> Vec<usize> {
+        (0..self.numbers.len())
+            .filter(|id| {
+                self.symbols_around(*id)
+                    .iter()
+                    .any(|(_, s)| rules.is_part_symbol(s))
+            })
+            .collect_vec()
+    }
+
+    // Gear coordinates with the ids of their adjacent numbers
+    fn gears(&self, rules: &Rules) -> Vec<((usize, usize), Vec<usize>)> {
+        self.symbols
+            .iter()
+            .filter(|(_, s)| **s == rules.gear_symbol)
+            .map(|((x, y), _)| ((*x, *y), self.numbers_around(*x, *y)))
+            .filter(|(_, ids)| ids.len() == rules.gear_neighbours)
+            .collect_vec()
+    }
+
+    // Symbols touching number `id`
+    fn symbols_around(&self, id: usize) -> Vec<((usize, usize), char)> {
+        let Number { x, y, len, .. } = self.numbers[id];
+        neighbours(x, y, len)
+            .into_iter()
+            .flat_map(|c| self.symbols.get(&c).map(|s| (c, *s)))
+            .collect_vec()
+    }
+}
+
+// Numbers end at the first non digit character or at the end of their row.
+// Rows may end with CRLF and trailing whitespace, whitespace inside a row is empty space.
+fn parse_board(input: &str) -> Result<Schematic> {
     let mut numbers = vec![];
     let mut symbols = HashMap::new();
-    let mut curr = String::new();
-    let (mut curr_x, mut curr_y) = (0, 0);
 
     for (x, line) in input.lines().enumerate() {
-        for (y, c) in line.chars().enumerate() {
-            let finish = match c {
-                '.' => true,
-                '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                    if curr.is_empty() {
-                        curr_x = x;
-                        curr_y = y;
-                    }
-                    curr.push(c);
-                    false
+        let mut cells = line.trim_end().chars().enumerate().peekable();
+        while let Some((y, c)) = cells.next() {
+            if c.is_ascii_digit() {
+                let mut digits = String::from(c);
+                while let Some((_, d)) = cells.next_if(|(_, d)| d.is_ascii_digit()) {
+                    digits.push(d);
                 }
-                _ => {
-                    symbols.insert((x, y), c);
-                    true
-                }
-            };
-            if finish && !curr.is_empty() {
-                numbers.push((curr.clone(), (curr_x, curr_y)));
-                curr = String::new();
+                numbers.push(Number {
+                    value: digits
+                        .parse()
+                        .with_context(|| format!("Invalid number '{digits}' at ({x}, {y})"))?,
+                    x,
+                    y,
+                    len: digits.len(),
+                });
+            } else if c != '.' && !c.is_whitespace() {
+                symbols.insert((x, y), c);
             }
         }
     }
 
-    (numbers, symbols)
+    Ok(Schematic::new(numbers, symbols))
+}
+
+fn part_1(schematic: &Schematic, rules: &Rules) -> Result<usize> {
+    Ok(schematic
+        .part_numbers(rules)
+        .iter()
+        .map(|id| schematic.numbers[*id].value)
+        .sum())
 }
 
-fn part_1(numbers: &Numbers, symbols: &Symbols) -> Result<usize> {
-    // let (numbers, symbols) = parse_board(input);
-    Ok(numbers
+fn part_2(schematic: &Schematic, rules: &Rules) -> Result<usize> {
+    Ok(schematic
+        .gears(rules)
         .iter()
-        .filter(|(num, (x, y))| {
-            neighbours(*x, *y, num.len())
+        .map(|(_, ids)| {
+            let values = ids
                 .iter()
-                .any(|c| symbols.get(c).is_some())
+                .map(|id| schematic.numbers[*id].value)
+                .collect_vec();
+            rules.combine.apply(&values)
         })
-        .flat_map(|(num, _)| num.parse::<usize>())
         .sum())
 }
 
-fn part_2(numbers: &Numbers, symbols: &Symbols) -> Result<usize> {
-    // let (numbers, symbols) = parse_board(input);
+// RENDERING
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ansi,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ansi" => Ok(Self::Ansi),
+            "html" => Ok(Self::Html),
+            _ => bail!("Unknown render format: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Part,
+    NonPart,
+    Gear,
+    Other,
+}
 
-    let mut gears = HashMap::new();
-    for (num, (x, y)) in numbers.iter() {
-        for coords in neighbours(*x, *y, num.len()) {
-            if let Some('*') = symbols.get(&coords) {
-                let n = num.parse::<usize>()?;
-                gears.entry(coords).or_insert_with(Vec::new).push(n);
+impl Cell {
+    fn ansi(&self) -> &str {
+        match self {
+            Self::Part => "\x1b[32m",
+            Self::NonPart => "\x1b[31m",
+            Self::Gear => "\x1b[1;33m",
+            Self::Other => "",
+        }
+    }
+
+    fn html(&self) -> &str {
+        match self {
+            Self::Part => "part",
+            Self::NonPart => "non-part",
+            Self::Gear => "gear",
+            Self::Other => "",
+        }
+    }
+
+    // Same palette as the ANSI colours
+    fn css(&self) -> &str {
+        match self {
+            Self::Part => "color: green;",
+            Self::NonPart => "color: red;",
+            Self::Gear => "color: goldenrod; font-weight: bold;",
+            Self::Other => "",
+        }
+    }
+}
+
+// Reprints the schematic with part numbers, non part numbers and gears highlighted
+fn render(input: &str, schematic: &Schematic, rules: &Rules, format: Format) -> String {
+    let parts: HashSet<_> = schematic.part_numbers(rules).into_iter().collect();
+    let gears: HashSet<_> = schematic.gears(rules).into_iter().map(|(c, _)| c).collect();
+
+    let mut out = String::new();
+    if format == Format::Html {
+        out.push_str("<style>\n");
+        for cell in [Cell::Part, Cell::NonPart, Cell::Gear] {
+            out.push_str(&format!(".{} {{ {} }}\n", cell.html(), cell.css()));
+        }
+        out.push_str("</style>\n<pre>\n");
+    }
+
+    for (x, line) in input.lines().enumerate() {
+        let cells = line.trim_end().chars().enumerate().map(|(y, c)| {
+            let cell = match schematic.cells.get(&(x, y)) {
+                Some(id) if parts.contains(id) => Cell::Part,
+                Some(_) => Cell::NonPart,
+                None if gears.contains(&(x, y)) => Cell::Gear,
+                None => Cell::Other,
+            };
+            (cell, c)
+        });
+
+        for (cell, run) in cells.group_by(|(cell, _)| *cell).into_iter() {
+            let text: String = run.map(|(_, c)| c).collect();
+            match (format, cell) {
+                (_, Cell::Other) => out.push_str(&escape(&text, format)),
+                (Format::Ansi, _) => out.push_str(&format!("{}{text}\x1b[0m", cell.ansi())),
+                (Format::Html, _) => out.push_str(&format!(
+                    "<span class=\"{}\">{}</span>",
+                    cell.html(),
+                    escape(&text, format)
+                )),
             }
         }
+        out.push('\n');
     }
 
-    Ok(gears
-        .iter()
-        .filter(|(_, v)| v.len() == 2)
-        .map(|(_, v)| v[0] * v[1])
-        .sum())
+    if format == Format::Html {
+        out.push_str("</pre>\n");
+    }
+
+    out
+}
+
+fn escape(text: &str, format: Format) -> String {
+    match format {
+        Format::Ansi => text.to_string(),
+        Format::Html => text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;"),
+    }
 }
 
 #[cfg(test)]
@@ -113,25 +381,174 @@ mod tests {
 
     #[test]
     fn test_1() {
-        let (n, s) = parse_board(TEST_1);
-        assert_eq!(4361, part_1(&n, &s).unwrap());
+        let schematic = parse_board(TEST_1).unwrap();
+        assert_eq!(4361, part_1(&schematic, &Rules::default()).unwrap());
     }
 
     #[test]
     fn test_input_1() {
-        let (n, s) = parse_board(INPUT);
-        assert_eq!(530495, part_1(&n, &s).unwrap());
+        let schematic = parse_board(INPUT).unwrap();
+        assert_eq!(530495, part_1(&schematic, &Rules::default()).unwrap());
     }
 
     #[test]
     fn test_2() {
-        let (n, s) = parse_board(TEST_1);
-        assert_eq!(467835, part_2(&n, &s).unwrap());
+        let schematic = parse_board(TEST_1).unwrap();
+        assert_eq!(467835, part_2(&schematic, &Rules::default()).unwrap());
     }
 
     #[test]
     fn test_input_2() {
-        let (n, s) = parse_board(INPUT);
-        assert_eq!(80253814, part_2(&n, &s).unwrap());
+        let schematic = parse_board(INPUT).unwrap();
+        assert_eq!(80253814, part_2(&schematic, &Rules::default()).unwrap());
+    }
+
+    #[test]
+    fn test_rules() {
+        let schematic = parse_board(TEST_1).unwrap();
+
+        let rules = Rules {
+            part_symbols: Some(HashSet::from(['#', '$'])),
+            ..Default::default()
+        };
+        assert_eq!(633 + 664, part_1(&schematic, &rules).unwrap());
+
+        let rules = Rules {
+            combine: Combine::Sum,
+            ..Default::default()
+        };
+        assert_eq!(467 + 35 + 755 + 598, part_2(&schematic, &rules).unwrap());
+
+        let rules = Rules {
+            gear_neighbours: 1,
+            combine: Combine::Max,
+            ..Default::default()
+        };
+        assert_eq!(617, part_2(&schematic, &rules).unwrap());
+
+        let rules = Rules {
+            gear_symbol: '+',
+            gear_neighbours: 1,
+            ..Default::default()
+        };
+        assert_eq!(592, part_2(&schematic, &rules).unwrap());
+    }
+
+    #[test]
+    fn test_queries() {
+        let schematic = parse_board(TEST_1).unwrap();
+        let values = |ids: Vec<usize>| {
+            ids.iter()
+                .map(|id| schematic.numbers[*id].value)
+                .sorted()
+                .collect_vec()
+        };
+
+        assert_eq!(vec![35, 467], values(schematic.numbers_around(1, 3)));
+        assert_eq!(vec![617], values(schematic.numbers_around(4, 3)));
+        assert_eq!(Vec::<usize>::new(), values(schematic.numbers_around(0, 9)));
+
+        let id = schematic.cells[&(2, 7)];
+        assert_eq!(633, schematic.numbers[id].value);
+        assert_eq!(vec![((3, 6), '#')], schematic.symbols_around(id));
+
+        let id = schematic.cells[&(0, 5)];
+        assert_eq!(114, schematic.numbers[id].value);
+        assert!(schematic.symbols_around(id).is_empty());
+    }
+
+    #[test]
+    fn test_row_end() {
+        let schematic = parse_board("..12\n.34*\n").unwrap();
+        assert_eq!(
+            vec![12, 34],
+            schematic.numbers.iter().map(|n| n.value).collect_vec()
+        );
+        assert_eq!(12 + 34, part_1(&schematic, &Rules::default()).unwrap());
+        assert_eq!(12 * 34, part_2(&schematic, &Rules::default()).unwrap());
+    }
+
+    #[test]
+    fn test_file_end() {
+        let schematic = parse_board("...*\n..42").unwrap();
+        assert_eq!(
+            vec![Number {
+                value: 42,
+                x: 1,
+                y: 2,
+                len: 2
+            }],
+            schematic.numbers
+        );
+        assert_eq!(42, part_1(&schematic, &Rules::default()).unwrap());
+    }
+
+    #[test]
+    fn test_crlf() {
+        let schematic = parse_board(&TEST_1.replace('\n', "\r\n")).unwrap();
+        assert_eq!(10, schematic.numbers.len());
+        assert_eq!(6, schematic.symbols.len());
+        assert_eq!(4361, part_1(&schematic, &Rules::default()).unwrap());
+        assert_eq!(467835, part_2(&schematic, &Rules::default()).unwrap());
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        let schematic = parse_board("12*34  \t\n.5 \n").unwrap();
+        assert_eq!(
+            vec![12, 34, 5],
+            schematic.numbers.iter().map(|n| n.value).collect_vec()
+        );
+        assert_eq!(
+            vec![((0, 2), '*')],
+            schematic.symbols.into_iter().collect_vec()
+        );
+    }
+
+    #[test]
+    fn test_inner_whitespace() {
+        let schematic = parse_board("12 ...\n..\t.34\n......").unwrap();
+        assert!(schematic.symbols.is_empty());
+        assert_eq!(0, part_1(&schematic, &Rules::default()).unwrap());
+
+        let schematic = parse_board("12 #\n 5\t*6").unwrap();
+        assert_eq!(
+            vec![((0, 3), '#'), ((1, 3), '*')],
+            schematic
+                .symbols
+                .iter()
+                .map(|(c, s)| (*c, *s))
+                .sorted()
+                .collect_vec()
+        );
+        assert_eq!(6, part_1(&schematic, &Rules::default()).unwrap());
+    }
+
+    #[test]
+    fn test_render() {
+        let input = "467..114..\n...*......\n..35..<...";
+        let schematic = parse_board(input).unwrap();
+
+        let rules = Rules::default();
+        assert_eq!(
+            "\x1b[32m467\x1b[0m..\x1b[31m114\x1b[0m..\n\
+             ...\x1b[1;33m*\x1b[0m......\n\
+             ..\x1b[32m35\x1b[0m..<...\n",
+            render(input, &schematic, &rules, Format::Ansi)
+        );
+
+        assert_eq!(
+            "<style>\n\
+             .part { color: green; }\n\
+             .non-part { color: red; }\n\
+             .gear { color: goldenrod; font-weight: bold; }\n\
+             </style>\n\
+             <pre>\n\
+             <span class=\"part\">467</span>..<span class=\"non-part\">114</span>..\n\
+             ...<span class=\"gear\">*</span>......\n\
+             ..<span class=\"part\">35</span>..&lt;...\n\
+             </pre>\n",
+            render(input, &schematic, &rules, Format::Html)
+        );
     }
 }
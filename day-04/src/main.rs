@@ -1,6 +1,11 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Range,
+    str::FromStr,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
 use nom::{
     bytes::complete::tag,
     character::complete::{digit1, newline, space1},
@@ -26,38 +31,376 @@ fn main() -> Result<()> {
     let (remaining, cards) = parse_cards(INPUT)?;
     assert!(remaining.is_empty(), "Remaining: '{remaining}'");
 
+    // Usage:
+    //   day-04 [--points linear|doubling|fibonacci] [--copies next|wraparound|capped]
+    //   day-04 explain --card N [--depth D] [--dot] [--copies next|wraparound|capped]
+    let args = std::env::args().skip(1).collect_vec();
+    if args.first().is_some_and(|a| a == "explain") {
+        let (mut card, mut depth, mut dot, mut copies) = (None, 2, false, Copies::Next);
+        let mut args = args.into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--card" => card = Some(args.next().context("Missing card id")?.parse()?),
+                "--depth" => depth = args.next().context("Missing depth")?.parse()?,
+                "--dot" => dot = true,
+                "--copies" => copies = args.next().context("Missing copies rule")?.parse()?,
+                _ => bail!("Unknown option: {arg}"),
+            }
+        }
+
+        let card = card.context("Missing --card")?;
+        if dot {
+            print!("{}", cascade_dot(&cards, card, &copies)?);
+        } else {
+            print!("{}", explain(&cards, card, depth, &copies)?);
+        }
+        return Ok(());
+    }
+
+    let (mut points, mut copies) = (Points::Doubling, Copies::Next);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--points" => points = value()?.parse()?,
+            "--copies" => copies = value()?.parse()?,
+            _ => bail!("Unknown option: {arg}"),
+        }
+    }
+
     println!("Day 04");
-    println!("\t1: {}", part_1(&cards)?);
-    println!("\t2: {}", part_2(&cards)?);
+    println!("\t1: {}", part_1(&cards, &points)?);
+    println!("\t2: {}", part_2(&cards, &copies)?);
 
     Ok(())
 }
 
-fn part_1(cards: &Cards) -> Result<usize> {
+fn part_1(cards: &Cards, rule: &impl PointRule) -> Result<usize> {
     Ok(cards
         .iter()
         .map(|(_, win, draw)| draw.iter().filter(move |d| win.contains(d)).count())
-        .flat_map(|c| (c > 0).then_some(1 << c.saturating_sub(1)))
+        .map(|c| rule.points(c))
         .sum())
 }
 
-fn part_2(cards: &Cards) -> Result<usize> {
-    let wins: HashMap<usize, usize> = HashMap::from_iter(
+// Every card is scratched once, in id order, and its copies scratched at the same time.
+// Copies won for cards that were already scratched (with wraparound) do not win anything.
+fn part_2(cards: &Cards, rule: &impl CopyRule) -> Result<usize> {
+    let totals = count_copies(cards, rule)?;
+    Ok(totals.len() + totals.iter().sum::<usize>())
+}
+
+// SCORING RULES
+
+trait PointRule {
+    fn points(&self, matches: usize) -> usize;
+}
+
+// Positions of the cards copied by the card at `position` out of `n` cards: the cards
+// following it, and the cards from the first one when going past the last card
+trait CopyRule {
+    fn copies(
+        &self,
+        position: usize,
+        matches: usize,
+        n: usize,
+    ) -> Result<(Range<usize>, Range<usize>)>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Points {
+    Linear,
+    Doubling,
+    Fibonacci,
+}
+
+impl FromStr for Points {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "doubling" => Ok(Self::Doubling),
+            "fibonacci" => Ok(Self::Fibonacci),
+            _ => bail!("Unknown points rule: {s}"),
+        }
+    }
+}
+
+impl PointRule for Points {
+    fn points(&self, matches: usize) -> usize {
+        match self {
+            Self::Linear => matches,
+            Self::Doubling => {
+                if matches > 0 {
+                    1 << (matches - 1)
+                } else {
+                    0
+                }
+            }
+            // 1, 1, 2, 3, 5, ...
+            Self::Fibonacci => (0..matches).fold((0, 1), |(a, b), _| (b, a + b)).0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Copies {
+    // Copy the next N cards, which must exist
+    Next,
+    // Copy the next N cards, going back to the first card after the last one.
+    // A card never copies itself so at most n - 1 cards are copied
+    Wraparound,
+    // Copy the next N cards, stopping at the last card
+    Capped,
+}
+
+impl FromStr for Copies {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "next" => Ok(Self::Next),
+            "wraparound" => Ok(Self::Wraparound),
+            "capped" => Ok(Self::Capped),
+            _ => bail!("Unknown copies rule: {s}"),
+        }
+    }
+}
+
+impl CopyRule for Copies {
+    fn copies(
+        &self,
+        position: usize,
+        matches: usize,
+        n: usize,
+    ) -> Result<(Range<usize>, Range<usize>)> {
+        let start = position + 1;
+        match self {
+            Self::Next if start + matches > n => {
+                bail!("Card at position {position} copies past the last card")
+            }
+            Self::Next => Ok((start..start + matches, 0..0)),
+            Self::Wraparound => {
+                let end = start + matches.min(n - 1);
+                Ok((start..end.min(n), 0..end.saturating_sub(n)))
+            }
+            Self::Capped => Ok((start..(start + matches).min(n), 0..0)),
+        }
+    }
+}
+
+// Number of matching numbers on each card
+fn count_wins(cards: &Cards) -> HashMap<usize, usize> {
+    HashMap::from_iter(
         cards
             .iter()
             .map(|(id, wins, draw)| (*id, draw.iter().filter(move |d| wins.contains(d)).count())),
+    )
+}
+
+// (id, number of matches) sorted by card id, card ids must be consecutive
+fn sorted_wins(cards: &Cards) -> Result<Vec<(usize, usize)>> {
+    let wins = count_wins(cards).into_iter().sorted().collect_vec();
+
+    if let Some(((a, _), (b, _))) = wins
+        .iter()
+        .tuple_windows()
+        .find(|((a, _), (b, _))| a + 1 != *b)
+    {
+        bail!("Card ids must be consecutive, found {a} followed by {b}")
+    }
+
+    Ok(wins)
+}
+
+// Total number of cards won from each card (sorted by id) in a single backwards pass.
+// suffix[i] holds the sum of the cards won from cards i..n so that the cards won
+// from the following cards a..b are suffix[a] - suffix[b]. Copies of cards that were
+// already scratched (with wraparound) do not win anything.
+fn count_copies(cards: &Cards, rule: &impl CopyRule) -> Result<Vec<usize>> {
+    let wins = sorted_wins(cards)?;
+
+    let n = wins.len();
+    let mut totals = vec![0; n];
+    let mut suffix = vec![0; n + 1];
+    for (i, (_, w)) in wins.iter().enumerate().rev() {
+        let (following, wrapped) = rule.copies(i, *w, n)?;
+        totals[i] =
+            following.len() + suffix[following.start] - suffix[following.end] + wrapped.len();
+        suffix[i] = suffix[i + 1] + totals[i];
+    }
+
+    Ok(totals)
+}
+
+// EXPLANATIONS
+
+// Card id -> matching numbers
+type Matches = HashMap<usize, Vec<usize>>;
+// Card id -> ids of the cards it copies, the following ones and the wrapped around ones
+type Children = HashMap<usize, (Vec<usize>, Vec<usize>)>;
+// Card id -> total number of cards won
+type Totals = HashMap<usize, usize>;
+
+fn cascade_info(cards: &Cards, rule: &impl CopyRule) -> Result<(Matches, Children, Totals)> {
+    let matches = cards
+        .iter()
+        .map(|(id, wins, draw)| {
+            (
+                *id,
+                draw.iter()
+                    .filter(|d| wins.contains(d))
+                    .copied()
+                    .collect_vec(),
+            )
+        })
+        .collect();
+    let wins = sorted_wins(cards)?;
+    let first = wins.first().map_or(0, |(id, _)| *id);
+    let children = wins
+        .iter()
+        .enumerate()
+        .map(|(i, (id, w))| {
+            let (following, wrapped) = rule.copies(i, *w, wins.len())?;
+            let ids = |range: Range<usize>| range.map(|p| first + p).collect_vec();
+            Ok((*id, (ids(following), ids(wrapped))))
+        })
+        .collect::<Result<_>>()?;
+    let totals = wins
+        .iter()
+        .map(|(id, _)| *id)
+        .zip(count_copies(cards, rule)?)
+        .collect();
+
+    Ok((matches, children, totals))
+}
+
+// Explains how many cards a single card yields, with its expansion tree truncated at `depth`
+fn explain(cards: &Cards, id: usize, depth: usize, rule: &impl CopyRule) -> Result<String> {
+    let (matches, children, totals) = cascade_info(cards, rule)?;
+    let Some(matched) = matches.get(&id) else {
+        bail!("Unknown card id: {id}")
+    };
+
+    let mut out = format!(
+        "Card {id}: {} matching numbers ({})\n",
+        matched.len(),
+        matched.iter().join(", ")
     );
+    let (following, wrapped) = &children[&id];
+    if !following.is_empty() || !wrapped.is_empty() {
+        out.push_str(&format!(
+            "Copies cards {}\n",
+            following.iter().chain(wrapped).join(", ")
+        ));
+    }
+    out.push_str(&format!("Total cards won: {}\n", totals[&id]));
+
+    out.push_str(&format!("Card {id} (+{})\n", totals[&id]));
+    explain_subtree(id, &children, &totals, depth, "", &mut out);
 
-    let mut cache = HashMap::new();
-    let res: usize = cards
+    Ok(out)
+}
+
+// Copies of cards that were already scratched are leaves, they do not win anything
+fn explain_subtree(
+    id: usize,
+    children: &Children,
+    totals: &Totals,
+    depth: usize,
+    prefix: &str,
+    out: &mut String,
+) {
+    let (following, wrapped) = &children[&id];
+    let copies = following
         .iter()
-        .flat_map(|(id, _, _)| get_subtree(id, &wins, &mut cache))
-        .sum();
+        .map(|c| (c, false))
+        .chain(wrapped.iter().map(|c| (c, true)))
+        .collect_vec();
+    if depth == 0 {
+        if !copies.is_empty() {
+            out.push_str(&format!("{prefix}└── ... {} more cards\n", totals[&id]));
+        }
+        return;
+    }
 
-    Ok(res + cards.len())
+    for (i, (child, scratched)) in copies.iter().enumerate() {
+        let last = i == copies.len() - 1;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        if *scratched {
+            out.push_str(&format!(
+                "{prefix}{branch}Card {child} (+0, already scratched)\n"
+            ));
+            continue;
+        }
+        out.push_str(&format!(
+            "{prefix}{branch}Card {child} (+{})\n",
+            totals[*child]
+        ));
+        explain_subtree(
+            **child,
+            children,
+            totals,
+            depth - 1,
+            &format!("{prefix}{indent}"),
+            out,
+        );
+    }
+}
+
+// Graphviz DOT graph of the cards reachable from a card, each card appears once.
+// Copies of cards that were already scratched are dashed edges and are not followed.
+fn cascade_dot(cards: &Cards, id: usize, rule: &impl CopyRule) -> Result<String> {
+    let (matches, children, totals) = cascade_info(cards, rule)?;
+    if !matches.contains_key(&id) {
+        bail!("Unknown card id: {id}")
+    }
+
+    let mut reachable = BTreeSet::from([id]);
+    let mut expanded = BTreeSet::new();
+    let mut stack = vec![id];
+    while let Some(card) = stack.pop() {
+        if !expanded.insert(card) {
+            continue;
+        }
+        let (following, wrapped) = &children[&card];
+        reachable.extend(following.iter().chain(wrapped));
+        stack.extend(following);
+    }
+
+    let mut out = String::from("digraph cascade {\n");
+    for card in reachable.iter() {
+        out.push_str(&format!(
+            "    {card} [label=\"Card {card}\\n{} matches\\n+{} cards\"];\n",
+            matches[card].len(),
+            totals[card]
+        ));
+    }
+    for card in expanded.iter() {
+        let (following, wrapped) = &children[card];
+        for child in following {
+            out.push_str(&format!("    {card} -> {child};\n"));
+        }
+        for child in wrapped {
+            out.push_str(&format!("    {card} -> {child} [style=dashed];\n"));
+        }
+    }
+    out.push_str("}\n");
+
+    Ok(out)
 }
 
 // Compute the total number of cards returned from a single scratchcard recursively (with caching)
+// Superseded by count_copies, kept as a reference implementation
+#[cfg(test)]
 fn get_subtree(
     id: &usize,
     wins: &HashMap<usize, usize>,
@@ -116,23 +459,189 @@ mod tests {
     #[test]
     fn test_1() {
         let (_, cards) = parse_cards(TEST_1).unwrap();
-        assert_eq!(13, part_1(&cards).unwrap());
+        assert_eq!(13, part_1(&cards, &Points::Doubling).unwrap());
     }
 
     #[test]
     fn test_input_1() {
         let (_, cards) = parse_cards(INPUT).unwrap();
-        assert_eq!(21485, part_1(&cards).unwrap());
+        assert_eq!(21485, part_1(&cards, &Points::Doubling).unwrap());
     }
     #[test]
     fn test_2() {
         let (_, cards) = parse_cards(TEST_1).unwrap();
-        assert_eq!(30, part_2(&cards).unwrap());
+        assert_eq!(30, part_2(&cards, &Copies::Next).unwrap());
     }
 
     #[test]
     fn test_input_2() {
         let (_, cards) = parse_cards(INPUT).unwrap();
-        assert_eq!(11024379, part_2(&cards).unwrap());
+        assert_eq!(11024379, part_2(&cards, &Copies::Next).unwrap());
+    }
+
+    // Cards with pseudo random numbers of matches, never winning past the last card
+    fn generate_cascade(n: usize, seed: u64) -> Cards {
+        let mut state = seed;
+        (1..=n)
+            .map(|id| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let w = ((state >> 33) as usize % 3).min(n - id);
+                (id, (0..w).collect_vec(), (0..8).collect_vec())
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn test_iterative() {
+        for (n, seed) in [(1, 0), (10, 1), (100, 2), (1000, 3)] {
+            let cards = generate_cascade(n, seed);
+            let wins = count_wins(&cards);
+            let mut cache = HashMap::new();
+            let recursive: usize = cards
+                .iter()
+                .flat_map(|(id, _, _)| get_subtree(id, &wins, &mut cache))
+                .sum();
+
+            assert_eq!(recursive + n, part_2(&cards, &Copies::Next).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let n = 300_000;
+        let cards = (1..=n)
+            .map(|id| (id, vec![1], if id < n { vec![1] } else { vec![] }))
+            .collect_vec();
+
+        assert_eq!(n * (n - 1) / 2 + n, part_2(&cards, &Copies::Next).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_ids() {
+        assert!(part_2(&vec![(1, vec![1], vec![1])], &Copies::Next).is_err());
+        assert!(count_copies(&vec![(1, vec![1], vec![1])], &Copies::Next).is_err());
+        assert!(part_2(
+            &vec![(1, vec![], vec![]), (3, vec![], vec![])],
+            &Copies::Next
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let (_, cards) = parse_cards(TEST_1).unwrap();
+
+        assert_eq!(
+            "Card 3: 2 matching numbers (21, 1)
+Copies cards 4, 5
+Total cards won: 3
+Card 3 (+3)
+├── Card 4 (+1)
+│   └── Card 5 (+0)
+└── Card 5 (+0)
+",
+            explain(&cards, 3, 2, &Copies::Next).unwrap()
+        );
+
+        assert_eq!(
+            "Card 3: 2 matching numbers (21, 1)
+Copies cards 4, 5
+Total cards won: 3
+Card 3 (+3)
+├── Card 4 (+1)
+│   └── ... 1 more cards
+└── Card 5 (+0)
+",
+            explain(&cards, 3, 1, &Copies::Next).unwrap()
+        );
+
+        assert!(explain(&cards, 7, 2, &Copies::Next).is_err());
+
+        // Cards 1, 2 and 3 win 3 matches each
+        let cards = (1..=3)
+            .map(|id| (id, vec![1, 2, 3], vec![1, 2, 3]))
+            .collect_vec();
+        assert_eq!(
+            "Card 2: 3 matching numbers (1, 2, 3)
+Copies cards 3, 1
+Total cards won: 4
+Card 2 (+4)
+├── Card 3 (+2)
+│   ├── Card 1 (+0, already scratched)
+│   └── Card 2 (+0, already scratched)
+└── Card 1 (+0, already scratched)
+",
+            explain(&cards, 2, 2, &Copies::Wraparound).unwrap()
+        );
+        assert!(explain(&cards, 2, 2, &Copies::Next).is_err());
+    }
+
+    #[test]
+    fn test_dot() {
+        let (_, cards) = parse_cards(TEST_1).unwrap();
+
+        let dot = cascade_dot(&cards, 1, &Copies::Next).unwrap();
+        // Header, cards 1 to 5, 4 + 2 + 2 + 1 edges and closing brace
+        assert_eq!(1 + 5 + 9 + 1, dot.lines().count());
+
+        assert_eq!(
+            "digraph cascade {
+    4 [label=\"Card 4\\n1 matches\\n+1 cards\"];
+    5 [label=\"Card 5\\n0 matches\\n+0 cards\"];
+    4 -> 5;
+}
+",
+            cascade_dot(&cards, 4, &Copies::Next).unwrap()
+        );
+
+        let cards = (1..=3)
+            .map(|id| (id, vec![1, 2, 3], vec![1, 2, 3]))
+            .collect_vec();
+        let dot = cascade_dot(&cards, 3, &Copies::Wraparound).unwrap();
+        assert!(dot.contains("    3 -> 1 [style=dashed];\n    3 -> 2 [style=dashed];\n"));
+        assert!(!dot.contains("1 ->"));
+    }
+
+    #[test]
+    fn test_points() {
+        let (_, cards) = parse_cards(TEST_1).unwrap();
+
+        let cases = [
+            (Points::Linear, [0, 1, 2, 3, 4, 5, 6], 4 + 2 + 2 + 1),
+            (Points::Doubling, [0, 1, 2, 4, 8, 16, 32], 8 + 2 + 2 + 1),
+            (Points::Fibonacci, [0, 1, 1, 2, 3, 5, 8], 3 + 1 + 1 + 1),
+        ];
+
+        for (rule, points, total) in cases {
+            assert_eq!(points, std::array::from_fn(|m| rule.points(m)));
+            assert_eq!(total, part_1(&cards, &rule).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_copies() {
+        let (_, cards) = parse_cards(TEST_1).unwrap();
+
+        assert_eq!((4..6, 0..1), Copies::Wraparound.copies(3, 3, 6).unwrap());
+        assert_eq!((1..3, 0..0), Copies::Wraparound.copies(0, 5, 3).unwrap());
+        assert_eq!((3..3, 0..2), Copies::Wraparound.copies(2, 5, 3).unwrap());
+        assert_eq!((4..6, 0..0), Copies::Capped.copies(3, 3, 6).unwrap());
+        assert!(Copies::Next.copies(3, 3, 6).is_err());
+
+        // No card copies past the last one, so every rule agrees
+        assert_eq!(30, part_2(&cards, &Copies::Wraparound).unwrap());
+        assert_eq!(30, part_2(&cards, &Copies::Capped).unwrap());
+
+        // Cards 1, 2 and 3 win 3 matches each
+        let cards = (1..=3)
+            .map(|id| (id, vec![1, 2, 3], vec![1, 2, 3]))
+            .collect_vec();
+        assert!(part_2(&cards, &Copies::Next).is_err());
+        // [1, 2, 2] -> [3, 2, 4] -> [7, 6, 4], copies of scratched cards do not win anything
+        assert_eq!(17, part_2(&cards, &Copies::Wraparound).unwrap());
+        // [1, 2, 2] -> [1, 2, 4] -> [1, 2, 4]
+        assert_eq!(7, part_2(&cards, &Copies::Capped).unwrap());
     }
 }
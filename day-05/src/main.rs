@@ -1,13 +1,13 @@
-use std::ops::Range;
+use std::{fmt::Display, ops::Range};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use nom::{
-    bytes::complete::{tag, take, take_till},
-    character::complete::{digit1, newline, space1},
-    combinator::map_res,
-    multi::{many1, separated_list1},
-    sequence::{delimited, preceded, tuple},
+    bytes::complete::tag,
+    character::complete::{alpha1, digit1, newline, space1},
+    combinator::{consumed, map_res},
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
@@ -53,77 +53,426 @@ type MapEntry<T> = (Range<T>, Range<T>);
 type Map<T> = Vec<MapEntry<T>>;
 
 fn main() -> Result<()> {
-    let (remaining, seeds) = parse_seeds(INPUT).context("Error parsing seeds")?;
-    let (_, maps) =
-        separated_list1(many1(newline), parse_map)(remaining).context("Erorr parsing maps")?;
+    let almanac = parse_almanac(INPUT)?;
+    let diagnostics = check(&almanac);
+
+    // Usage: day-05 [check | --dump | --locations START END | --query FROM TO VALUE]
+    let args = std::env::args().skip(1).collect_vec();
+    let checking = args.first().is_some_and(|a| a == "check");
+    if checking {
+        for diagnostic in diagnostics.iter() {
+            println!("{diagnostic}");
+        }
+        if diagnostics.is_empty() {
+            println!("No issues found");
+        }
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.level == Level::Error)
+        .collect_vec();
+    if !errors.is_empty() {
+        bail!("Invalid almanac:\n{}", errors.iter().join("\n"))
+    }
+    if checking {
+        return Ok(());
+    }
+
+    let seeds = &almanac.seeds;
+    let maps = almanac.chain("seed", "location")?;
+
+    match args.first().map(String::as_str) {
+        Some("--query") => {
+            let (from, to) = (
+                args.get(1).context("Missing source category")?,
+                args.get(2).context("Missing destination category")?,
+            );
+            let value = args.get(3).context("Missing value")?.parse()?;
+            let composed = Piecewise::compose_all(&almanac.chain(from, to)?);
+            println!("{from} {value} -> {to} {}", composed.apply(value));
+            return Ok(());
+        }
+        Some("--dump") => {
+            print!("{}", Piecewise::compose_all(&maps));
+            return Ok(());
+        }
+        Some("--locations") => {
+            let start = args.get(1).context("Missing start location")?.parse()?;
+            let end = args.get(2).context("Missing end location")?.parse()?;
+            for seeds in seeds_for_locations(&maps, start..end) {
+                println!("{}..{}", seeds.start, seeds.end);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
     println!("Day 05");
-    println!("\t1: {}", part_1(&seeds, &maps)?);
-    println!("\t2: {}", part_2(&seeds, &maps)?);
+    println!("\t1: {}", part_1(seeds, &maps)?);
+    println!("\t2: {}", part_2(seeds, &maps)?);
 
     Ok(())
 }
 
 fn part_1(seeds: &[usize], maps: &[Map<usize>]) -> Result<usize> {
-    let mut curr = seeds.iter().cloned().collect_vec();
-    for map in maps.iter() {
-        curr = curr
+    let composed = Piecewise::compose_all(maps);
+
+    seeds
+        .iter()
+        .map(|seed| composed.apply(*seed))
+        .min()
+        .context("Error getting minimum location")
+}
+
+fn part_2(seeds: &[usize], maps: &[Map<usize>]) -> Result<usize> {
+    let composed = Piecewise::compose_all(maps);
+
+    seeds
+        .chunks(2)
+        .flat_map(|s| composed.apply_range(s[0]..(s[0] + s[1])))
+        .map(|range| range.start)
+        .min()
+        .context("Error getting minimum location")
+}
+
+// ALMANAC
+
+#[derive(Debug, Clone)]
+struct NamedMap {
+    source: String,
+    destination: String,
+    map: Map<usize>,
+    // Line of the map header, entries are on the following lines
+    line: usize,
+}
+
+// Maps form a graph between categories, each category has at most one map from it and one map
+// to it and there are no cycles, so the graph is a set of chains
+#[derive(Debug)]
+struct Almanac {
+    seeds: Vec<usize>,
+    maps: Vec<NamedMap>,
+}
+
+impl Almanac {
+    fn new(seeds: Vec<usize>, maps: Vec<NamedMap>) -> Result<Self> {
+        for (a, b) in maps.iter().tuple_combinations() {
+            if a.source == b.source {
+                bail!("Duplicated maps from category '{}'", a.source)
+            }
+            if a.destination == b.destination {
+                bail!("Duplicated maps to category '{}'", a.destination)
+            }
+        }
+
+        let almanac = Self { seeds, maps };
+        for start in almanac.maps.iter() {
+            let mut curr = start;
+            for _ in 0..almanac.maps.len() {
+                match almanac.map_from(&curr.destination) {
+                    Some(next) => curr = next,
+                    None => break,
+                }
+            }
+            if almanac.map_from(&curr.destination).is_some() {
+                bail!("Cycle between categories from '{}'", start.source)
+            }
+        }
+
+        Ok(almanac)
+    }
+
+    fn map_from(&self, category: &str) -> Option<&NamedMap> {
+        self.maps.iter().find(|m| m.source == category)
+    }
+
+    fn has_category(&self, category: &str) -> bool {
+        self.maps
             .iter()
-            .map(|seed| {
-                match map
+            .any(|m| m.source == category || m.destination == category)
+    }
+
+    // Maps to apply in order to go from one category to another
+    fn chain(&self, from: &str, to: &str) -> Result<Vec<Map<usize>>> {
+        for category in [from, to] {
+            if !self.has_category(category) {
+                bail!("Missing category '{category}'")
+            }
+        }
+
+        let mut maps = vec![];
+        let mut curr = from;
+        while curr != to {
+            let next = self
+                .map_from(curr)
+                .with_context(|| format!("No path from '{from}' to '{to}'"))?;
+            maps.push(next.map.clone());
+            curr = &next.destination;
+        }
+
+        Ok(maps)
+    }
+}
+
+// VALIDATION
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Diagnostic {
+    level: Level,
+    line: usize,
+    message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        write!(f, "line {}: {level}: {}", self.line, self.message)
+    }
+}
+
+// Overlapping source ranges are errors since the mapped value depends on entry order,
+// overlapping destinations, gaps and zero length entries are only warnings
+fn check(almanac: &Almanac) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let diagnostic = |level, line, message| Diagnostic {
+        level,
+        line,
+        message,
+    };
+
+    for named in almanac.maps.iter() {
+        let name = format!("{}-to-{}", named.source, named.destination);
+        let entries = named
+            .map
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (named.line + 1 + i, entry))
+            .collect_vec();
+
+        for (line, (src, _)) in entries.iter() {
+            if src.is_empty() {
+                diagnostics.push(diagnostic(
+                    Level::Warning,
+                    *line,
+                    format!("{name}: zero length entry"),
+                ));
+            }
+        }
+
+        for ((l1, (src1, dest1)), (l2, (src2, dest2))) in entries.iter().tuple_combinations() {
+            if let Some(inter) = range_intersect(src1.clone(), src2.clone()) {
+                diagnostics.push(diagnostic(
+                    Level::Error,
+                    *l2,
+                    format!(
+                        "{name}: source range {}..{} overlaps line {l1} on {}..{}",
+                        src2.start, src2.end, inter.start, inter.end
+                    ),
+                ));
+            }
+            if let Some(inter) = range_intersect(dest1.clone(), dest2.clone()) {
+                diagnostics.push(diagnostic(
+                    Level::Warning,
+                    *l2,
+                    format!(
+                        "{name}: destination range {}..{} overlaps line {l1} on {}..{}",
+                        dest2.start, dest2.end, inter.start, inter.end
+                    ),
+                ));
+            }
+        }
+
+        let sources = entries
+            .iter()
+            .filter(|(_, (src, _))| !src.is_empty())
+            .sorted_by_key(|(_, (src, _))| src.start)
+            .collect_vec();
+        let mut covered = sources.first().map_or(0, |(_, (src, _))| src.end);
+        for (line, (src, _)) in sources.iter().skip(1) {
+            if covered < src.start {
+                diagnostics.push(diagnostic(
+                    Level::Warning,
+                    *line,
+                    format!("{name}: gap {}..{} is not mapped", covered, src.start),
+                ));
+            }
+            covered = covered.max(src.end);
+        }
+    }
+
+    diagnostics
+}
+
+// Source range and the offset applied to values in that range
+type Segment = (Range<usize>, i128);
+
+// Normalized piecewise linear map: sorted, contiguous and non overlapping segments covering
+// every value, values outside of the original map entries are in identity (0 offset) segments
+#[derive(Debug, Clone, PartialEq)]
+struct Piecewise {
+    segments: Vec<Segment>,
+}
+
+impl Piecewise {
+    fn identity() -> Self {
+        Self {
+            segments: vec![(0..usize::MAX, 0)],
+        }
+    }
+
+    // When entries overlap the first matching entry wins, as in the puzzle
+    fn from_map(map: &Map<usize>) -> Self {
+        let bounds = map
+            .iter()
+            .flat_map(|(src, _)| [src.start, src.end])
+            .chain([0, usize::MAX])
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let segments = bounds
+            .into_iter()
+            .tuple_windows()
+            .map(|(start, end)| {
+                let offset = map
                     .iter()
-                    .map(|(src, dest)| src.contains(seed).then(|| dest.start + seed - src.start))
-                    .find(|v| v.is_some())
-                {
-                    None => *seed,
-                    Some(Some(x)) => x,
-                    _ => unreachable!(),
-                }
+                    .find(|(src, _)| src.contains(&start))
+                    .map_or(0, |(src, dest)| dest.start as i128 - src.start as i128);
+                (start..end, offset)
             })
             .collect_vec();
+
+        Self::normalized(segments)
     }
 
-    curr.into_iter()
-        .min()
-        .context("Error getting minimum location")
-}
+    // Sorts segments and merges neighbours that have the same offset
+    fn normalized(segments: Vec<Segment>) -> Self {
+        let mut merged: Vec<Segment> = vec![];
+        for (range, offset) in segments.into_iter().sorted_by_key(|(r, _)| r.start) {
+            match merged.last_mut() {
+                Some((last, o)) if *o == offset && last.end == range.start => last.end = range.end,
+                _ => merged.push((range, offset)),
+            }
+        }
 
-fn part_2(seeds: &[usize], maps: &[Map<usize>]) -> Result<usize> {
-    let mut seed_ranges = seeds.chunks(2).map(|s| (s[0]..(s[0] + s[1]))).collect_vec();
+        Self { segments: merged }
+    }
 
-    for map in maps.iter() {
-        seed_ranges = seed_ranges
+    // Map equivalent to applying self and then other
+    fn then(&self, other: &Self) -> Self {
+        let segments = self
+            .segments
             .iter()
-            .flat_map(|seed_range| {
-                let mut valid_ranges = vec![];
-                let mut remainders = vec![seed_range.clone()];
-
-                for (inter, mapped) in map.iter().flat_map(move |(src, dest)| {
-                    range_intersect(seed_range.clone(), src.clone()).map(|inter| {
-                        let off_s = inter.start - src.start;
-                        let off_e = src.end - inter.end;
-
-                        (inter, (dest.start + off_s)..(dest.end - off_e))
-                    })
-                }) {
-                    valid_ranges.push(mapped);
-                    remainders = remainders
-                        .iter()
-                        .flat_map(|r| exclude_from_range(r.clone(), inter.clone()))
-                        .collect_vec();
-                }
+            .flat_map(|(range, offset)| {
+                let image = shift(range, *offset);
+                other
+                    .overlapping(&image)
+                    .iter()
+                    .flat_map(|(r, o)| range_intersect(image.clone(), r.clone()).map(|i| (i, *o)))
+                    .map(|(inter, o)| (shift(&inter, -offset), offset + o))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        Self::normalized(segments)
+    }
+
+    fn compose_all(maps: &[Map<usize>]) -> Self {
+        maps.iter()
+            .fold(Self::identity(), |acc, map| acc.then(&Self::from_map(map)))
+    }
 
-                valid_ranges.extend_from_slice(&remainders[..]);
-                valid_ranges
+    // Segments overlapping a range
+    fn overlapping(&self, range: &Range<usize>) -> &[Segment] {
+        let start = self.segments.partition_point(|(r, _)| r.end <= range.start);
+        let end = self.segments.partition_point(|(r, _)| r.start < range.end);
+        &self.segments[start..end.max(start)]
+    }
+
+    // Values past the last segment, i.e. usize::MAX, are left unchanged
+    fn apply(&self, value: usize) -> usize {
+        let i = self.segments.partition_point(|(r, _)| r.end <= value);
+        match self.segments.get(i) {
+            Some((range, offset)) if range.contains(&value) => (value as i128 + offset) as usize,
+            _ => value,
+        }
+    }
+
+    fn apply_range(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        self.overlapping(&range)
+            .iter()
+            .flat_map(|(r, o)| range_intersect(range.clone(), r.clone()).map(|i| shift(&i, *o)))
+            .collect_vec()
+    }
+}
+
+impl Piecewise {
+    // Every value mapped into `range`, inverting each segment. Identity segments are inverted
+    // like any other so values reached both through an entry and through a gap are all found
+    fn preimage(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let ranges = self
+            .segments
+            .iter()
+            .flat_map(|(r, o)| {
+                range_intersect(shift(r, *o), range.clone()).map(|inter| shift(&inter, -o))
             })
             .collect_vec();
+
+        merge_ranges(ranges)
+    }
+}
+
+// Seed ranges leading to a location range, propagated backwards through each map
+fn seeds_for_locations(maps: &[Map<usize>], locations: Range<usize>) -> Vec<Range<usize>> {
+    maps.iter().rev().fold(vec![locations], |ranges, map| {
+        let piecewise = Piecewise::from_map(map);
+        merge_ranges(
+            ranges
+                .into_iter()
+                .flat_map(|range| piecewise.preimage(range))
+                .collect_vec(),
+        )
+    })
+}
+
+// Sorts ranges and merges the ones that overlap or touch
+fn merge_ranges(ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges.into_iter().sorted_by_key(|r| r.start) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
     }
+    merged
+}
 
-    seed_ranges
-        .into_iter()
-        .map(|range| range.start)
-        .min()
-        .context("Error getting minimum location")
+impl Display for Piecewise {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (range, offset) in self.segments.iter() {
+            let dest = shift(range, *offset);
+            writeln!(
+                f,
+                "{:>12}..{:<20} -> {:>12}..{:<20} ({offset:+})",
+                range.start, range.end, dest.start, dest.end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn shift(range: &Range<usize>, offset: i128) -> Range<usize> {
+    (range.start as i128 + offset) as usize..(range.end as i128 + offset) as usize
 }
 
 fn range_intersect<T: PartialOrd + Ord>(a: Range<T>, b: Range<T>) -> Option<Range<T>> {
@@ -137,30 +486,35 @@ fn range_intersect<T: PartialOrd + Ord>(a: Range<T>, b: Range<T>) -> Option<Rang
     Some(s..e)
 }
 
-fn exclude_from_range<T: PartialOrd + Ord>(
-    reference: Range<T>,
-    excluding: Range<T>,
-) -> Vec<Range<T>> {
-    let mut splits = vec![];
-
-    if reference.start < excluding.start {
-        splits.push(reference.start..excluding.start)
-    }
-
-    if excluding.end < reference.end {
-        splits.push(excluding.end..reference.end)
-    }
-
-    splits
-}
-
 // Parsing functions
 
-fn parse_ranges(input: &str) -> IResult<&str, MapEntry<usize>> {
-    let (r, nums) = separated_list1(space1, map_res(digit1, str::parse))(input)?;
-    let (dest, src, len) = (nums[0], nums[1], nums[2]);
+// Entries are checked once their line number is known, see parse_entry
+fn parse_ranges(input: &str) -> IResult<&str, Vec<&str>> {
+    separated_list1(space1, digit1)(input)
+}
 
-    Ok((r, (src..(src + len), dest..(dest + len))))
+// Map entry from its "destination source length" numbers
+fn parse_entry(numbers: &[&str], line: usize) -> Result<MapEntry<usize>> {
+    let numbers: Vec<usize> = numbers
+        .iter()
+        .map(|n| {
+            n.parse()
+                .with_context(|| format!("Line {line}: invalid number {n}"))
+        })
+        .try_collect()?;
+    let [dest, src, len] = numbers[..] else {
+        bail!(
+            "Line {line}: expected 3 numbers in map entry, found {}",
+            numbers.len()
+        )
+    };
+    let end = |start: usize| {
+        start
+            .checked_add(len)
+            .with_context(|| format!("Line {line}: range {start} + {len} overflows"))
+    };
+
+    Ok((src..end(src)?, dest..end(dest)?))
 }
 
 fn parse_seeds(input: &str) -> IResult<&str, Vec<usize>> {
@@ -171,22 +525,62 @@ fn parse_seeds(input: &str) -> IResult<&str, Vec<usize>> {
     )(input)
 }
 
-fn parse_map(input: &str) -> IResult<&str, Map<usize>> {
-    let (r, _) = take_till(|c| c == ':')(input)?;
-    let (r, _) = take(1usize)(r)?; // Consume ':'
-    preceded(newline, separated_list1(newline, parse_ranges))(r)
+// (source, destination, entries)
+type RawMap<'a> = (&'a str, &'a str, Vec<Vec<&'a str>>);
+
+fn parse_map(input: &str) -> IResult<&str, RawMap<'_>> {
+    let (r, (source, destination)) =
+        terminated(separated_pair(alpha1, tag("-to-"), alpha1), tag(" map:"))(input)?;
+    let (r, entries) = preceded(newline, separated_list1(newline, parse_ranges))(r)?;
+
+    Ok((r, (source, destination, entries)))
+}
+
+fn parse_almanac(input: &str) -> Result<Almanac> {
+    let (remaining, seeds) = parse_seeds(input)
+        .map_err(|e| e.to_owned())
+        .context("Error parsing seeds")?;
+    let (remaining, maps) = preceded(
+        many0(newline),
+        separated_list1(many1(newline), consumed(parse_map)),
+    )(remaining)
+    .map_err(|e| e.to_owned())
+    .context("Error parsing maps")?;
+
+    let maps: Vec<NamedMap> = maps
+        .into_iter()
+        .map(|(text, (source, destination, entries))| -> Result<_> {
+            let offset = text.as_ptr() as usize - input.as_ptr() as usize;
+            let line = input[..offset].matches('\n').count() + 1;
+            Ok(NamedMap {
+                source: source.to_string(),
+                destination: destination.to_string(),
+                map: entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, numbers)| parse_entry(numbers, line + 1 + i))
+                    .try_collect()?,
+                line,
+            })
+        })
+        .try_collect()?;
+
+    if !remaining.trim().is_empty() {
+        bail!("Could not parse almanac from: '{remaining}'")
+    }
+
+    Almanac::new(seeds, maps)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn parse(input: &'static str) -> Result<(Vec<usize>, Vec<Map<usize>>)> {
-        let (remaining, seeds) = parse_seeds(input).context("Error parsing seeds")?;
-        let (_, maps) =
-            separated_list1(many1(newline), parse_map)(remaining).context("Erorr parsing maps")?;
+    fn parse(input: &str) -> Result<(Vec<usize>, Vec<Map<usize>>)> {
+        let almanac = parse_almanac(input)?;
+        let maps = almanac.chain("seed", "location")?;
 
-        Ok((seeds, maps))
+        Ok((almanac.seeds, maps))
     }
 
     #[test]
@@ -227,4 +621,227 @@ mod tests {
         let (seeds, maps) = parse(INPUT).unwrap();
         assert_eq!(50855035, part_2(&seeds, &maps).unwrap())
     }
+
+    #[test]
+    fn test_compose() {
+        let (seeds, maps) = parse(TEST_1).unwrap();
+        let composed = Piecewise::compose_all(&maps);
+
+        assert_eq!(
+            vec![82, 43, 86, 35],
+            seeds.iter().map(|s| composed.apply(*s)).collect_vec()
+        );
+
+        // Segments are sorted, contiguous, cover every value and have distinct neighbours
+        assert_eq!(0, composed.segments[0].0.start);
+        assert_eq!(usize::MAX, composed.segments.last().unwrap().0.end);
+        for ((a, o1), (b, o2)) in composed.segments.iter().tuple_windows() {
+            assert_eq!(a.end, b.start);
+            assert_ne!(o1, o2);
+        }
+
+        // Applying maps one at a time gives the same result
+        for seed in 0..200 {
+            let chained = maps
+                .iter()
+                .fold(seed, |v, map| Piecewise::from_map(map).apply(v));
+            assert_eq!(chained, composed.apply(seed));
+        }
+
+        let mapped = composed
+            .apply_range(55..68)
+            .into_iter()
+            .flatten()
+            .sorted()
+            .collect_vec();
+        let wanted = (55..68).map(|s| composed.apply(s)).sorted().collect_vec();
+        assert_eq!(wanted, mapped);
+    }
+
+    #[test]
+    fn test_overlapping_entries() {
+        // First matching entry wins
+        let map = vec![(0..10, 100..110), (5..15, 200..210)];
+        let piecewise = Piecewise::from_map(&map);
+        assert_eq!(
+            vec![(0..10, 100), (10..15, 195), (15..usize::MAX, 0)],
+            piecewise.segments
+        );
+    }
+
+    #[test]
+    fn test_inverse() {
+        let (_, maps) = parse(TEST_1).unwrap();
+        let composed = Piecewise::compose_all(&maps);
+
+        // Lowest location of part 1 comes from seed 13
+        assert!(seeds_for_locations(&maps, 35..36).contains(&(13..14)));
+
+        for locations in [0..10, 35..36, 46..47, 50..80, 90..120] {
+            let inverse = seeds_for_locations(&maps, locations.clone());
+            assert_eq!(composed.preimage(locations.clone()), inverse);
+
+            // forward(inverse(x)) ⊆ x
+            for range in inverse.iter() {
+                for mapped in composed.apply_range(range.clone()) {
+                    assert!(locations.start <= mapped.start && mapped.end <= locations.end);
+                }
+            }
+
+            // Nothing outside of the inverse maps into x
+            for seed in 0..200 {
+                let inside = inverse.iter().any(|r| r.contains(&seed));
+                assert_eq!(locations.contains(&composed.apply(seed)), inside);
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_values() {
+        let high = 1 << 63;
+        let map = vec![
+            (high..high + 10, 0..10),
+            (0..10, usize::MAX - 10..usize::MAX),
+        ];
+        let piecewise = Piecewise::from_map(&map);
+        assert_eq!(3, piecewise.apply(high + 3));
+        assert_eq!(usize::MAX - 7, piecewise.apply(3));
+        assert_eq!(high + 10, piecewise.apply(high + 10));
+        assert_eq!(usize::MAX, piecewise.apply(usize::MAX));
+        assert_eq!(vec![0..5], piecewise.apply_range(high..high + 5));
+        assert_eq!(vec![high..high + 10], piecewise.preimage(0..10));
+
+        // Round trip through both ends of the value range
+        let composed = piecewise.then(&piecewise);
+        assert_eq!(usize::MAX - 10, composed.apply(high));
+        assert_eq!(usize::MAX - 1, Piecewise::identity().apply(usize::MAX - 1));
+    }
+
+    #[test]
+    fn test_identity_gaps() {
+        // 5..10 is mapped to 0..5 and also reached through the identity gap
+        let piecewise = Piecewise::from_map(&vec![(5..10, 0..5)]);
+        assert_eq!(vec![0..10], piecewise.preimage(0..5));
+        assert_eq!(Vec::<Range<usize>>::new(), piecewise.preimage(5..10));
+        assert_eq!(vec![10..12], piecewise.preimage(10..12));
+    }
+
+    #[test]
+    fn test_categories() {
+        let almanac = parse_almanac(TEST_1).unwrap();
+
+        let soil_to_humidity = Piecewise::compose_all(&almanac.chain("soil", "humidity").unwrap());
+        // Seed 79 -> soil 81 -> ... -> humidity 78
+        assert_eq!(78, soil_to_humidity.apply(81));
+        assert!(almanac.chain("seed", "seed").unwrap().is_empty());
+        assert!(almanac.chain("humidity", "soil").is_err());
+        assert!(almanac.chain("seed", "wormhole").is_err());
+    }
+
+    #[test]
+    fn test_unordered_maps() {
+        // Maps given in reverse order still chain from seed to location
+        let (seeds, sections) = TEST_1.split_once("\n\n").unwrap();
+        let reversed = format!(
+            "{seeds}\n\n{}",
+            sections
+                .split("\n\n")
+                .collect_vec()
+                .into_iter()
+                .rev()
+                .join("\n\n")
+        );
+
+        let (seeds, maps) = parse(&reversed).unwrap();
+        assert_eq!(35, part_1(&seeds, &maps).unwrap());
+        assert_eq!(46, part_2(&seeds, &maps).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_almanacs() {
+        let duplicated = "seeds: 1\n\na-to-b map:\n1 2 3\n\na-to-c map:\n1 2 3";
+        assert!(parse_almanac(duplicated).is_err());
+
+        let duplicated = "seeds: 1\n\na-to-b map:\n1 2 3\n\nc-to-b map:\n1 2 3";
+        assert!(parse_almanac(duplicated).is_err());
+
+        let cycle = "seeds: 1\n\na-to-b map:\n1 2 3\n\nb-to-a map:\n1 2 3";
+        assert!(parse_almanac(cycle).is_err());
+
+        let missing = "seeds: 1\n\nseed-to-soil map:\n1 2 3\n\nwater-to-location map:\n1 2 3";
+        let almanac = parse_almanac(missing).unwrap();
+        assert!(almanac.chain("seed", "location").is_err());
+
+        let cases = [
+            (
+                "seeds: 1\n\nseed-to-location map:\n1 2",
+                "Line 4: expected 3 numbers in map entry, found 2",
+            ),
+            (
+                "seeds: 1\n\nseed-to-location map:\n1 2 3\n1 2 3 4",
+                "Line 5: expected 3 numbers in map entry, found 4",
+            ),
+            (
+                "seeds: 1\n\nseed-to-location map:\n1 18446744073709551615 5",
+                "Line 4: range 18446744073709551615 + 5 overflows",
+            ),
+            (
+                "seeds: 1\n\nseed-to-location map:\n1 2 3\n\nx-to-y map:\n99999999999999999999 2 3",
+                "Line 7: invalid number 99999999999999999999",
+            ),
+        ];
+        for (input, message) in cases {
+            let error = parse_almanac(input).unwrap_err();
+            assert_eq!(message, error.to_string());
+        }
+
+        // Ranges may end exactly at usize::MAX
+        let edge = "seeds: 1\n\nseed-to-location map:\n0 18446744073709551610 5";
+        assert!(parse_almanac(edge).is_ok());
+    }
+
+    #[test]
+    fn test_check() {
+        let almanac = parse_almanac(TEST_1).unwrap();
+        assert!(check(&almanac).is_empty());
+
+        let almanac = parse_almanac(
+            "seeds: 1
+
+a-to-b map:
+0 10 5
+3 12 5
+20 30 0
+
+b-to-c map:
+0 0 5
+10 10 5",
+        )
+        .unwrap();
+        let diagnostics = check(&almanac)
+            .into_iter()
+            .map(|d| (d.level, d.line, d.message))
+            .collect_vec();
+        assert_eq!(
+            vec![
+                (Level::Warning, 6, "a-to-b: zero length entry".to_string()),
+                (
+                    Level::Error,
+                    5,
+                    "a-to-b: source range 12..17 overlaps line 4 on 12..15".to_string()
+                ),
+                (
+                    Level::Warning,
+                    5,
+                    "a-to-b: destination range 3..8 overlaps line 4 on 3..5".to_string()
+                ),
+                (
+                    Level::Warning,
+                    10,
+                    "b-to-c: gap 5..10 is not mapped".to_string()
+                ),
+            ],
+            diagnostics
+        );
+    }
 }
@@ -1,11 +1,12 @@
-use anyhow::{Context, Result};
+use std::ops::RangeInclusive;
+
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use nom::{
     bytes::complete::tag,
-    character::complete::{alpha1, digit1, newline, space1},
-    combinator::map_res,
+    character::complete::{digit1, line_ending, space0, space1},
     multi::separated_list1,
-    sequence::{separated_pair, tuple},
+    sequence::{preceded, separated_pair, tuple},
     IResult,
 };
 
@@ -16,92 +17,222 @@ Distance:  9  40  200";
 const INPUT: &str = include_str!("../../inputs/day-06.txt");
 
 fn main() -> Result<()> {
+    // Usage: day-06 [--acceleration A] [--max-speed S] [--drag D] [--charge C]
+    let mut boat = Boat::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value: u64 = args
+            .next()
+            .with_context(|| format!("Missing value for {arg}"))?
+            .parse()?;
+        match arg.as_str() {
+            "--acceleration" => boat.acceleration = value,
+            "--max-speed" => boat.max_speed = Some(value),
+            "--drag" => boat.drag = value,
+            "--charge" => boat.charge = value,
+            _ => bail!("Unknown option: {arg}"),
+        }
+    }
+
     println!("Day 06");
-    println!("\t1: {}", part_1(INPUT)?);
-    println!("\t2: {}", part_2(INPUT)?);
+    println!("\t1: {}", part_1(INPUT, &boat)?);
+    println!("\t2: {}", part_2(INPUT, &boat)?);
 
     Ok(())
 }
 
-fn part_1(input: &'static str) -> Result<i64> {
-    let (r, races) = parse_races(input)?;
-    assert!(r.is_empty(), "Did not parse everything: {r}");
+fn part_1(input: &str, boat: &Boat) -> Result<u64> {
+    let races = parse_races(input, false)?;
+    Ok(races.iter().map(|race| race.ways(boat)).product())
+}
 
-    Ok(races.iter().flat_map(|race| race.solve()).product())
+fn part_2(input: &str, boat: &Boat) -> Result<u64> {
+    let races = parse_races(input, true)?;
+    Ok(races.iter().map(|race| race.ways(boat)).product())
 }
 
-fn part_2(input: &str) -> Result<i64> {
-    let parsed = input
-        .lines()
-        .flat_map(|line| line.split_whitespace().skip(1).join("").parse())
-        .collect_vec();
+#[derive(Debug)]
+struct Race {
+    time: u64,
+    record: u64,
+}
 
-    Race {
-        time: parsed[0],
-        record: parsed[1],
+impl Race {
+    fn distance(&self, hold: u64) -> u128 {
+        hold as u128 * (self.time - hold) as u128
+    }
+
+    fn wins(&self, hold: u64) -> bool {
+        hold <= self.time && self.distance(hold) > self.record as u128
+    }
+
+    // Hold times beating the record: hold * (time - hold) > record. Solved exactly with the
+    // integer square root of the discriminant, the candidate is then nudged by at most one
+    // to account for the truncated square root and halving
+    fn solve(&self) -> Option<RangeInclusive<u64>> {
+        let (time, record) = (self.time as u128, self.record as u128);
+        let det = (time * time).checked_sub(4 * record)?;
+
+        let mut low = ((time - det.isqrt()) / 2) as u64;
+        while low > 0 && self.wins(low - 1) {
+            low -= 1;
+        }
+        while low <= self.time / 2 && !self.wins(low) {
+            low += 1;
+        }
+
+        // Distance is maximal at time / 2, if that doesn't win nothing does
+        if low > self.time / 2 {
+            return None;
+        }
+
+        // Distance is symmetric around time / 2
+        Some(low..=self.time - low)
     }
-    .solve()
-    .context("Could not solve race")
-}
 
-fn find_roots(a: i64, b: i64, c: i64) -> Option<(i64, i64)> {
-    let det = b.pow(2) - (4 * a * c);
+    // Hold times beating the record with a given boat, solved analytically when the distance is
+    // quadratic in the hold time and by binary searches on the unimodal distance otherwise
+    fn solve_with(&self, boat: &Boat) -> Option<RangeInclusive<u64>> {
+        if boat.max_speed.is_none() && boat.drag == 0 {
+            // acceleration * hold * (time - charge - hold) > record
+            if boat.acceleration == 0 {
+                return None;
+            }
+            return Race {
+                time: self.time.checked_sub(boat.charge)?,
+                record: self.record / boat.acceleration,
+            }
+            .solve();
+        }
 
-    match det.cmp(&0) {
-        std::cmp::Ordering::Less => None,
-        std::cmp::Ordering::Equal => Some((-b / (2 * a), -b / (2 * a))),
-        std::cmp::Ordering::Greater => {
-            let r1: f64 = (-(b as f64) - f64::sqrt(det as f64)) / (2. * a as f64);
-            let r2: f64 = (-(b as f64) + f64::sqrt(det as f64)) / (2. * a as f64);
+        self.search(boat)
+    }
 
-            Some((f64::ceil(r1.min(r2)) as i64, f64::floor(r1.max(r2)) as i64))
+    fn search(&self, boat: &Boat) -> Option<RangeInclusive<u64>> {
+        let last = self.time.checked_sub(boat.charge)?;
+        let distance = |hold| boat.distance(self.time, hold);
+        let record = self.record as u128;
+
+        // Distance increases (or stays at 0) up to its peak and strictly decreases after
+        let peak = partition_point(0, last, |h| distance(h + 1) >= distance(h));
+        if distance(peak) <= record {
+            return None;
         }
+
+        let low = partition_point(0, peak, |h| distance(h) <= record);
+        let high = partition_point(peak, last, |h| distance(h + 1) > record);
+
+        Some(low..=high)
+    }
+
+    fn ways(&self, boat: &Boat) -> u64 {
+        self.solve_with(boat)
+            .map_or(0, |range| range.end() - range.start() + 1)
     }
 }
 
-#[derive(Debug)]
-struct Race {
-    time: i64,
-    record: i64,
+// First value in start..=end for which pred is false, or end, pred must be true then false
+fn partition_point(start: u64, end: u64, pred: impl Fn(u64) -> bool) -> u64 {
+    let (mut low, mut high) = (start, end);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
 }
 
-impl Race {
-    fn solve(&self) -> Option<i64> {
-        find_roots(-1, self.time, -self.record).map(|(r1, r2)| {
-            let r1 = (r1 - 1..=r1 + 1)
-                .filter(|&hold| (self.time - hold) * hold > self.record)
-                .min()
-                .unwrap();
-            let r2 = (r2 - 1..=r2 + 1)
-                .filter(|&hold| (self.time - hold) * hold > self.record)
-                .max()
-                .unwrap();
-
-            r2 - r1 + 1
-        })
+// Holding the button increases the boat's speed by `acceleration` per ms up to `max_speed`.
+// Once released the boat waits `charge` ms before moving and loses `drag` speed every ms.
+#[derive(Debug, Clone, PartialEq)]
+struct Boat {
+    acceleration: u64,
+    max_speed: Option<u64>,
+    drag: u64,
+    charge: u64,
+}
+
+impl Default for Boat {
+    fn default() -> Self {
+        Self {
+            acceleration: 1,
+            max_speed: None,
+            drag: 0,
+            charge: 0,
+        }
+    }
+}
+
+impl Boat {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        let Some(travel) = time.checked_sub(hold + self.charge) else {
+            return 0;
+        };
+
+        let speed = hold as u128 * self.acceleration as u128;
+        let speed = self.max_speed.map_or(speed, |max| speed.min(max as u128));
+        let travel = travel as u128;
+        if self.drag == 0 {
+            return speed * travel;
+        }
+
+        // Speed decreases by drag every ms until the boat stops: n * speed - drag * n(n - 1) / 2
+        let drag = self.drag as u128;
+        let moving = travel.min(speed.div_ceil(drag));
+        moving * speed - drag * moving * moving.saturating_sub(1) / 2
     }
 }
 
-fn parse_numbers(input: &str) -> IResult<&str, Vec<i64>> {
-    separated_list1(space1, map_res(digit1, str::parse))(input)
+fn parse_row<'a>(header: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<&'a str>> {
+    preceded(
+        tuple((tag(header), tag(":"), space1)),
+        separated_list1(space1, digit1),
+    )
 }
 
-fn parse_races(input: &str) -> IResult<&str, Vec<Race>> {
-    let (r, races) = separated_list1(
-        newline,
-        separated_pair(tuple((alpha1, tag(":"))), space1, parse_numbers),
-    )(input)?;
-
-    Ok((
-        r,
-        races[0]
-            .1
-            .clone()
-            .into_iter()
-            .zip(races[1].1.clone())
-            .map(|(time, record)| Race { time, record })
-            .collect_vec(),
-    ))
+// With kerning the columns of each row are joined into a single number, giving a single race
+fn parse_races(input: &str, kerning: bool) -> Result<Vec<Race>> {
+    let (remaining, (times, records)) = separated_pair(
+        parse_row("Time"),
+        tuple((space0, line_ending)),
+        parse_row("Distance"),
+    )(input)
+    .map_err(|e| e.to_owned())
+    .context("Error parsing races")?;
+
+    if !remaining.trim().is_empty() {
+        bail!("Could not parse races from: '{remaining}'")
+    }
+    if times.len() != records.len() {
+        bail!(
+            "Found {} times but {} distances",
+            times.len(),
+            records.len()
+        )
+    }
+
+    let parse = |columns: Vec<&str>| -> Result<Vec<u64>> {
+        if kerning {
+            let joined = columns.join("");
+            Ok(vec![joined
+                .parse()
+                .with_context(|| format!("Invalid number: {joined}"))?])
+        } else {
+            columns
+                .into_iter()
+                .map(|c| c.parse().with_context(|| format!("Invalid number: {c}")))
+                .collect()
+        }
+    };
+
+    Ok(parse(times)?
+        .into_iter()
+        .zip(parse(records)?)
+        .map(|(time, record)| Race { time, record })
+        .collect_vec())
 }
 
 #[cfg(test)]
@@ -110,21 +241,183 @@ mod tests {
 
     #[test]
     fn test_1() {
-        assert_eq!(288, part_1(TEST_1).unwrap());
+        assert_eq!(288, part_1(TEST_1, &Boat::default()).unwrap());
     }
 
     #[test]
     fn test_input_1() {
-        assert_eq!(1710720, part_1(INPUT).unwrap());
+        assert_eq!(1710720, part_1(INPUT, &Boat::default()).unwrap());
     }
 
     #[test]
     fn test_2() {
-        assert_eq!(71503, part_2(TEST_1).unwrap());
+        assert_eq!(71503, part_2(TEST_1, &Boat::default()).unwrap());
     }
 
     #[test]
     fn test_input_2() {
-        assert_eq!(35349468, part_2(INPUT).unwrap());
+        assert_eq!(35349468, part_2(INPUT, &Boat::default()).unwrap());
+    }
+
+    #[test]
+    fn test_intervals() {
+        let cases = [
+            (7, 9, Some(2..=5)),
+            (15, 40, Some(4..=11)),
+            (30, 200, Some(11..=19)),
+            // Best distance equals the record: no way to win
+            (10, 25, None),
+            (10, 24, Some(5..=5)),
+            (0, 0, None),
+            (1, 0, None),
+            (2, 0, Some(1..=1)),
+        ];
+
+        for (time, record, wanted) in cases {
+            assert_eq!(wanted, Race { time, record }.solve());
+        }
+    }
+
+    #[test]
+    fn test_precision() {
+        // Values around and past the 53 bit mantissa of f64
+        let times = [
+            (1 << 53) - 1,
+            1 << 53,
+            (1 << 53) + 1,
+            (1 << 62) + 3,
+            u32::MAX as u64 * 2 + 1,
+            u64::MAX,
+            u64::MAX - 1,
+        ];
+
+        for time in times {
+            let half = time / 2;
+            let best = half as u128 * (time - half) as u128;
+            let clamp = |r: u128| r.min(u64::MAX as u128) as u64;
+            for record in [
+                0,
+                1,
+                time / 3,
+                time,
+                time.saturating_mul(7),
+                clamp(best / 2),
+                clamp(best - 1),
+            ] {
+                let race = Race { time, record };
+                let range = race.solve().unwrap();
+                assert!(race.wins(*range.start()) && race.wins(*range.end()));
+                assert!(*range.start() == 0 || !race.wins(range.start() - 1));
+                assert!(*range.end() == time || !race.wins(range.end() + 1));
+            }
+        }
+
+        // Only the middle hold time beats a record one below the best distance
+        let time = (1u64 << 32) - 2;
+        let best = (time as u128 / 2).pow(2);
+        let race = Race {
+            time,
+            record: (best - 1) as u64,
+        };
+        assert_eq!(Some(time / 2..=time / 2), race.solve());
+    }
+
+    #[test]
+    fn test_boats() {
+        let boats = [
+            Boat::default(),
+            Boat {
+                acceleration: 3,
+                charge: 2,
+                ..Default::default()
+            },
+            Boat {
+                max_speed: Some(6),
+                ..Default::default()
+            },
+            Boat {
+                drag: 1,
+                ..Default::default()
+            },
+            Boat {
+                acceleration: 2,
+                max_speed: Some(9),
+                drag: 3,
+                charge: 1,
+            },
+        ];
+
+        for boat in boats.iter() {
+            for (time, record) in [(7, 9), (15, 40), (30, 200), (30, 20), (50, 0), (3, 100)] {
+                let race = Race { time, record };
+                let brute = (0..=time)
+                    .filter(|h| boat.distance(time, *h) > record as u128)
+                    .collect_vec();
+                let wanted = brute.first().map(|l| *l..=*brute.last().unwrap());
+
+                // Winning hold times are contiguous
+                assert_eq!(
+                    brute.len() as u64,
+                    wanted.clone().map_or(0, |r| r.count() as u64)
+                );
+                assert_eq!(wanted, race.solve_with(boat), "{boat:?} {race:?}");
+                assert_eq!(wanted, race.search(boat), "{boat:?} {race:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_boat_distance() {
+        let boat = Boat {
+            drag: 2,
+            ..Default::default()
+        };
+        // Speed 5 with 10ms of travel: 5 + 3 + 1
+        assert_eq!(9, boat.distance(15, 5));
+        // Speed 5 with 2ms of travel: 5 + 3
+        assert_eq!(8, boat.distance(7, 5));
+
+        let boat = Boat {
+            max_speed: Some(4),
+            charge: 3,
+            ..Default::default()
+        };
+        assert_eq!(4 * 2, boat.distance(15, 10));
+        assert_eq!(0, boat.distance(15, 13));
+    }
+
+    #[test]
+    fn test_parsing() {
+        let races = parse_races(TEST_1, false).unwrap();
+        assert_eq!(
+            vec![(7, 9), (15, 40), (30, 200)],
+            races.iter().map(|r| (r.time, r.record)).collect_vec()
+        );
+
+        let races = parse_races(TEST_1, true).unwrap();
+        assert_eq!(
+            vec![(71530, 940200)],
+            races.iter().map(|r| (r.time, r.record)).collect_vec()
+        );
+
+        // CRLF and trailing whitespace
+        assert!(parse_races("Time: 7 15  \r\nDistance: 9 40\r\n", false).is_ok());
+
+        let invalid = [
+            "Time: 7 15\nDistance: 9",
+            "Distance: 9 40\nTime: 7 15",
+            "Time: 7 15\nRecord: 9 40",
+            "Time: 7 15\nDistance: 9 40\nTime: 1",
+            "Time: 7 15",
+            "Time: 7 x\nDistance: 9 40",
+        ];
+        for input in invalid {
+            assert!(parse_races(input, false).is_err(), "{input}");
+        }
+
+        // Columns overflow once joined
+        let input = "Time: 9999999999 9999999999\nDistance: 1 1";
+        assert!(parse_races(input, false).is_ok());
+        assert!(parse_races(input, true).is_err());
     }
 }
@@ -1,5 +1,16 @@
-use anyhow::{bail, Result};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
+use nom::{
+    bytes::complete::take_till1,
+    character::complete::{digit1, line_ending, space0, space1},
+    combinator::map_res,
+    multi::separated_list1,
+    sequence::{separated_pair, tuple},
+    IResult,
+};
+use serde::Deserialize;
 
 #[allow(dead_code)]
 const TEST_1: &str = "32T3K 765
@@ -10,229 +21,552 @@ QQQJA 483";
 const INPUT: &str = include_str!("../../inputs/day-07.txt");
 
 fn main() -> Result<()> {
+    // Usage: day-07 [lowest|natural|highest] [--rules FILE] [--explain]
+    //  - the value of jokers when breaking ties in part 2
+    //  - a TOML rule set to also score the input with, e.g.
+    //      order = "23456789TJQKA"
+    //      wildcards = "J"
+    //      wildcard_value = "lowest"
+    //      precedence = ["5", "4-1", "3-2", "3-1-1", "2-2-1", "2-1-1-1", "1-1-1-1-1"]
+    //  - list how every hand was ranked under the rule set, or under the part 2 rules
+    let mut value = WildcardValue::Lowest;
+    let mut rules = None;
+    let mut show_explanation = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rules" => {
+                let path = args.next().context("Missing rule set file")?;
+                rules = Some(RuleSet::load(&path)?);
+            }
+            "--explain" => show_explanation = true,
+            _ => value = arg.parse()?,
+        }
+    }
+
+    if show_explanation {
+        let rules = rules.unwrap_or_else(|| RuleSet::jokers(value));
+        for explanation in explain(INPUT, &rules)? {
+            println!("{explanation}");
+        }
+        return Ok(());
+    }
+
     println!("Day 07");
     println!("\t1: {}", part_1(INPUT)?);
-    println!("\t2: {}", part_2(INPUT)?);
+    println!("\t2: {}", part_2(INPUT, value)?);
+    if let Some(rules) = rules {
+        println!("\tRules: {}", winnings(INPUT, &rules)?);
+    }
 
     Ok(())
 }
 
+// Card ordering, from lowest to highest
+const STANDARD: &str = "23456789TJQKA";
+
+// Rank of the joker in the standard ordering
+fn joker() -> usize {
+    STANDARD.find('J').unwrap()
+}
+
 fn part_1(input: &str) -> Result<usize> {
-    Ok(input
-        .lines()
-        .map(|line| {
-            let mut chars = line.chars();
-
-            let cards: String = (0..5).flat_map(|_| chars.next()).collect();
-            let bid: usize = chars
-                .filter(|c| !c.is_whitespace())
-                .collect::<String>()
-                .parse()
-                .unwrap();
-
-            (Draw::from_str(&cards, false).unwrap(), bid)
-        })
-        .sorted_by(|(cards_a, _), (cards_b, _)| cards_a.cmp(cards_b))
+    winnings(input, &RuleSet::standard())
+}
+
+fn part_2(input: &str, value: WildcardValue) -> Result<usize> {
+    winnings(input, &RuleSet::jokers(value))
+}
+
+// Total winnings: each bid multiplied by the rank of its hand under the rules
+fn winnings(input: &str, rules: &RuleSet) -> Result<usize> {
+    let hands = parse_hands(input, &rules.order)?;
+
+    Ok(ranking(&hands, rules)?
+        .iter()
         .enumerate()
-        .map(|(i, (_, bid))| (i + 1) * bid)
+        .map(|(i, ranked)| (i + 1) * ranked.bid)
         .sum())
 }
 
-fn part_2(input: &str) -> Result<usize> {
-    Ok(input
-        .lines()
-        .map(|line| {
-            let mut chars = line.chars();
+// A hand as seen by a rule set
+#[derive(Debug)]
+struct Ranked<'a> {
+    draw: &'a Draw,
+    resolved: Draw,
+    strength: usize,
+    bid: usize,
+}
 
-            let draw = Draw::from_str(
-                &((0..5).flat_map(|_| chars.next()).collect::<String>()),
-                true,
-            )
-            .unwrap();
-            let bid: usize = chars
-                .filter(|c| !c.is_whitespace())
-                .collect::<String>()
-                .parse()
-                .unwrap();
+impl Ranked<'_> {
+    fn key(&self) -> (usize, &Hand, &Cards) {
+        (self.strength, &self.resolved.hand, &self.resolved.cards)
+    }
+}
 
-            (draw.resolve_joker(), bid)
+// Hands from weakest to strongest. Hands that compare equal keep their input order.
+fn ranking<'a>(hands: &'a [(Draw, usize)], rules: &RuleSet) -> Result<Vec<Ranked<'a>>> {
+    let ranked: Vec<Ranked> = hands
+        .iter()
+        .map(|(draw, bid)| -> Result<_> {
+            let resolved = rules.resolve(draw);
+            Ok(Ranked {
+                draw,
+                strength: rules.strength(&resolved.hand)?,
+                resolved,
+                bid: *bid,
+            })
         })
-        .sorted_by(|(cards_a, _), (cards_b, _)| cards_a.cmp(cards_b))
+        .try_collect()?;
+
+    Ok(ranked
+        .into_iter()
+        .sorted_by(|a, b| a.key().cmp(&b.key()))
+        .collect())
+}
+
+fn parse_hand(input: &str) -> IResult<&str, (&str, usize)> {
+    separated_pair(
+        take_till1(|c: char| c.is_whitespace()),
+        space1,
+        map_res(digit1, str::parse),
+    )(input)
+}
+
+// Hands and their bids, one per line, with cards ranked by the given ordering
+fn parse_hands(input: &str, order: &str) -> Result<Vec<(Draw, usize)>> {
+    let (remaining, hands) = separated_list1(tuple((space0, line_ending)), parse_hand)(input)
+        .map_err(|e| e.to_owned())
+        .context("Error parsing hands")?;
+
+    if !remaining.trim().is_empty() {
+        bail!("Could not parse hands from: '{remaining}'")
+    }
+
+    hands
+        .into_iter()
         .enumerate()
-        .map(|(i, (_, bid))| (i + 1) * bid)
-        .sum::<usize>())
+        .map(|(i, (cards, bid))| {
+            let draw = Draw::from_str(cards, order)
+                .with_context(|| format!("Invalid hand on line {}: {cards}", i + 1))?;
+            Ok((draw, bid))
+        })
+        .collect()
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
-struct Cards([u8; 5]);
+// How a hand was ranked, for --explain
+#[derive(Debug)]
+struct Explanation {
+    rank: usize,
+    cards: String,
+    detected: Hand,
+    resolved: Hand,
+    decision: Decision,
+    bid: usize,
+    // Rank of the first identical hand
+    duplicate_of: Option<usize>,
+}
 
-impl Cards {
-    fn from_str(s: &str, with_jokers: bool) -> Result<Self> {
-        if s.len() != 5 {
-            bail!("A Camel cards hand must have 5 cards")
-        }
+// What put a hand above the one ranked just below it
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Weakest,
+    HandType {
+        below: String,
+    },
+    Card {
+        below: String,
+        position: usize,
+        ours: char,
+        theirs: char,
+    },
+    Tie {
+        below: String,
+    },
+}
+
+fn explain(input: &str, rules: &RuleSet) -> Result<Vec<Explanation>> {
+    let hands = parse_hands(input, &rules.order)?;
+    let ranking = ranking(&hands, rules)?;
+    let mut first_ranks: HashMap<&Cards, usize> = HashMap::new();
 
-        let mut cards = [0; 5];
-        for (i, c) in s.chars().enumerate() {
-            cards[i] = match c {
-                'T' => 9,
-                'J' => {
-                    if with_jokers {
-                        0
+    Ok(ranking
+        .iter()
+        .enumerate()
+        .map(|(i, ranked)| {
+            let rank = i + 1;
+            let cards = ranked.draw.cards.repr(&rules.order);
+            let decision = match i.checked_sub(1).map(|j| &ranking[j]) {
+                None => Decision::Weakest,
+                Some(below) => {
+                    let below_cards = below.draw.cards.repr(&rules.order);
+                    if (ranked.strength, &ranked.resolved.hand)
+                        != (below.strength, &below.resolved.hand)
+                    {
+                        Decision::HandType { below: below_cards }
                     } else {
-                        10
+                        match ranked
+                            .resolved
+                            .cards
+                            .0
+                            .iter()
+                            .zip(&below.resolved.cards.0)
+                            .position(|(a, b)| a != b)
+                        {
+                            Some(position) => Decision::Card {
+                                ours: cards.chars().nth(position).unwrap(),
+                                theirs: below_cards.chars().nth(position).unwrap(),
+                                below: below_cards,
+                                position,
+                            },
+                            None => Decision::Tie { below: below_cards },
+                        }
                     }
                 }
-                'Q' => 11,
-                'K' => 12,
-                'A' => 13,
-                '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => (c as u32 - 48) as u8 - 1,
-                c => bail!("Unknown card type {c}"),
             };
+
+            Explanation {
+                rank,
+                cards,
+                detected: ranked.draw.hand.clone(),
+                resolved: ranked.resolved.hand.clone(),
+                decision,
+                bid: ranked.bid,
+                duplicate_of: match first_ranks.get(&ranked.draw.cards) {
+                    Some(first) => Some(*first),
+                    None => {
+                        first_ranks.insert(&ranked.draw.cards, rank);
+                        None
+                    }
+                },
+            }
+        })
+        .collect())
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>5} {} {:<15} -> {:<15} {:>4} x {:<5} = {:<8} {}",
+            self.rank,
+            self.cards,
+            self.detected.to_string(),
+            self.resolved.to_string(),
+            self.bid,
+            self.rank,
+            self.bid * self.rank,
+            self.decision,
+        )?;
+        if let Some(first) = self.duplicate_of {
+            write!(f, " (duplicate of rank {first})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Weakest => write!(f, "weakest hand"),
+            Decision::HandType { below } => write!(f, "beats {below} on hand type"),
+            Decision::Card {
+                below,
+                position,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "beats {below} on card {}: {ours} > {theirs}",
+                position + 1
+            ),
+            Decision::Tie { below } => write!(f, "ties with {below}, kept in input order"),
+        }
+    }
+}
+
+// How hands are compared: the card alphabet from lowest to highest, which cards are wildcards
+// and how they break ties, and optionally a custom order of hand types from strongest to weakest.
+// Without a custom order, hand types are ranked by their count signature.
+#[derive(Debug, Clone)]
+struct RuleSet {
+    order: String,
+    wildcards: Vec<usize>,
+    wildcard_value: WildcardValue,
+    precedence: Option<Vec<Hand>>,
+}
+
+// Rule set as written in a config file, before validation
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    order: Option<String>,
+    #[serde(default)]
+    wildcards: String,
+    wildcard_value: Option<String>,
+    precedence: Option<Vec<String>>,
+}
+
+impl RuleSet {
+    fn standard() -> Self {
+        Self {
+            order: STANDARD.to_string(),
+            wildcards: vec![],
+            wildcard_value: WildcardValue::Natural,
+            precedence: None,
+        }
+    }
+
+    fn jokers(wildcard_value: WildcardValue) -> Self {
+        Self {
+            wildcards: vec![joker()],
+            wildcard_value,
+            ..Self::standard()
+        }
+    }
+
+    fn load(path: &str) -> Result<Self> {
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read rule set {path}"))?;
+        Self::from_toml(&config).with_context(|| format!("Invalid rule set {path}"))
+    }
+
+    fn from_toml(config: &str) -> Result<Self> {
+        Self::from_config(toml::from_str(config)?)
+    }
+
+    fn from_config(config: RuleConfig) -> Result<Self> {
+        let order = config.order.unwrap_or_else(|| STANDARD.to_string());
+        if order.is_empty() {
+            bail!("The card order must have at least one card")
+        }
+        if let Some(c) = order.chars().duplicates().next() {
+            bail!("Card {c} appears twice in the card order")
+        }
+
+        let wildcards = match config.wildcards.is_empty() {
+            true => vec![],
+            false => Cards::from_str(&config.wildcards, &order)?
+                .0
+                .into_iter()
+                .unique()
+                .collect(),
+        };
+
+        let wildcard_value = match config.wildcard_value {
+            Some(value) => value.parse()?,
+            None => WildcardValue::Lowest,
+        };
+
+        let precedence = match config.precedence {
+            Some(precedence) => {
+                let hands: Vec<Hand> = precedence.iter().map(|h| h.parse()).try_collect()?;
+                if let Some(hand) = hands.iter().duplicates().next() {
+                    bail!("Hand type {hand} appears twice in the precedence")
+                }
+                Some(hands)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            order,
+            wildcards,
+            wildcard_value,
+            precedence,
+        })
+    }
+
+    fn resolve(&self, draw: &Draw) -> Draw {
+        match self.wildcards.is_empty() {
+            true => draw.clone(),
+            false => draw.resolve_wildcards(&self.wildcards, self.wildcard_value),
+        }
+    }
+
+    // Strength of a hand type under a custom precedence, higher is stronger. All hand types
+    // are equally strong without one, leaving their count signature to decide.
+    fn strength(&self, hand: &Hand) -> Result<usize> {
+        let Some(precedence) = &self.precedence else {
+            return Ok(0);
+        };
+
+        match precedence.iter().position(|h| h == hand) {
+            Some(i) => Ok(precedence.len() - i),
+            None => bail!("Hand type {hand} is missing from the precedence"),
+        }
+    }
+}
+
+// Rank of each card in the hand, i.e. its position in the card ordering
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+struct Cards(Vec<usize>);
+
+impl Cards {
+    fn from_str(s: &str, order: &str) -> Result<Self> {
+        if s.is_empty() {
+            bail!("A Camel cards hand must have at least one card")
         }
 
+        let cards = s
+            .chars()
+            .map(|c| match order.chars().position(|o| o == c) {
+                Some(rank) => Ok(rank),
+                None => bail!("Unknown card type {c}"),
+            })
+            .try_collect()?;
+
         Ok(Self(cards))
     }
+
+    // Cards as written with the given ordering
+    fn repr(&self, order: &str) -> String {
+        self.0
+            .iter()
+            .map(|&rank| order.chars().nth(rank).unwrap_or('?'))
+            .collect()
+    }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+// Hands are ordered by type first and then by comparing cards one by one
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 struct Draw {
     hand: Hand,
     cards: Cards,
-    s: String,
 }
 
 impl Draw {
-    fn from_str(s: &str, with_jokers: bool) -> Result<Self> {
-        let cards = Cards::from_str(s, with_jokers)?;
+    fn from_str(s: &str, order: &str) -> Result<Self> {
+        let cards = Cards::from_str(s, order)?;
         let hand = Hand::from_cards(&cards);
 
-        Ok(Self {
-            hand,
-            cards,
-            s: s.to_string(),
-        })
+        Ok(Self { hand, cards })
     }
 
-    fn resolve_joker(&self) -> Self {
-        let n_jokers = self.cards.0.iter().filter(|&v| v == &0).count();
+    // Best hand obtainable by turning wildcards into any card: wildcards always join the largest
+    // group of other cards. Resolved cards are shifted by one so that wildcards can be valued
+    // below every other card when breaking ties.
+    fn resolve_wildcards(&self, wildcards: &[usize], value: WildcardValue) -> Self {
+        let is_wild = |c: &usize| wildcards.contains(c);
+        let others = Cards(
+            self.cards
+                .0
+                .iter()
+                .copied()
+                .filter(|c| !is_wild(c))
+                .collect(),
+        );
+        let n_wild = self.cards.0.len() - others.0.len();
 
-        let new_hand = match n_jokers {
-            4 => Hand::Five,
-            3 => match self.hand {
-                Hand::FullHouse => Hand::Five,
-                Hand::Three => Hand::Four,
-                _ => unreachable!(),
-            },
-            2 => match self.hand {
-                Hand::FullHouse => Hand::Five,
-                Hand::DoublePair => Hand::Four,
-                Hand::Pair => Hand::Three,
-                _ => unreachable!(),
-            },
-            1 => match self.hand {
-                Hand::Four => Hand::Five,
-                Hand::Three => Hand::Four,
-                Hand::Pair => Hand::Three,
-                Hand::DoublePair => Hand::FullHouse,
-                Hand::HighCard => Hand::Pair,
-                _ => unreachable!(),
-            },
-            _ => self.hand,
-        };
+        let mut counts = Hand::from_cards(&others).0;
+        match counts.first_mut() {
+            Some(top) => *top += n_wild,
+            None => counts.push(n_wild),
+        }
+
+        let cards = self
+            .cards
+            .0
+            .iter()
+            .map(|c| match value {
+                WildcardValue::Lowest if is_wild(c) => 0,
+                WildcardValue::Highest if is_wild(c) => usize::MAX,
+                _ => c + 1,
+            })
+            .collect();
 
         Self {
-            hand: new_hand,
-            cards: self.cards,
-            s: self.s.clone(),
+            hand: Hand(counts),
+            cards: Cards(cards),
         }
     }
 }
 
-impl PartialOrd for Draw {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(match self.hand.cmp(&other.hand) {
-            std::cmp::Ordering::Equal => self
-                .cards
-                .0
-                .iter()
-                .zip(other.cards.0)
-                .map(|(c1, c2)| c1.cmp(&c2))
-                .find(|cmp| !matches!(cmp, std::cmp::Ordering::Equal))
-                .unwrap_or(std::cmp::Ordering::Equal),
-            ord => ord,
-        })
-    }
+// Value of wildcards when comparing cards one by one
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WildcardValue {
+    Natural,
+    Lowest,
+    Highest,
 }
 
-impl Ord for Draw {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let Some(ord) = self.partial_cmp(other) else {
-            unreachable!()
-        };
-        ord
+impl FromStr for WildcardValue {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "natural" => Ok(Self::Natural),
+            "lowest" => Ok(Self::Lowest),
+            "highest" => Ok(Self::Highest),
+            _ => bail!("Unknown wildcard value: {s}"),
+        }
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
-enum Hand {
-    HighCard,
-    Pair,
-    DoublePair,
-    Three,
-    FullHouse,
-    Four,
-    Five,
-}
+// Count signature of a hand: number of cards of each kind in decreasing order.
+// Comparing signatures lexicographically ranks hand types for any hand size,
+// e.g. for 5 cards: [5] > [4, 1] > [3, 2] > [3, 1, 1] > [2, 2, 1] > [2, 1, 1, 1] > [1, 1, 1, 1, 1]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+struct Hand(Vec<usize>);
 
 impl Hand {
-    fn hand_order(&self) -> u8 {
-        match self {
-            Self::HighCard => 0,
-            Self::Pair => 1,
-            Self::DoublePair => 2,
-            Self::Three => 3,
-            Self::FullHouse => 4,
-            Self::Four => 5,
-            Self::Five => 6,
-        }
+    fn from_cards(cards: &Cards) -> Self {
+        Self(
+            cards
+                .0
+                .iter()
+                .counts()
+                .into_values()
+                .sorted()
+                .rev()
+                .collect(),
+        )
     }
+}
 
-    fn from_cards(cards: &Cards) -> Self {
-        let mut counter = [0; 14];
-        for card in cards.0.iter() {
-            counter[*card as usize] += 1;
-        }
+// Names of the usual 5-card hand types
+const HAND_NAMES: [(&[usize], &str); 7] = [
+    (&[5], "Five of a kind"),
+    (&[4, 1], "Four of a kind"),
+    (&[3, 2], "Full house"),
+    (&[3, 1, 1], "Three of a kind"),
+    (&[2, 2, 1], "Two pair"),
+    (&[2, 1, 1, 1], "One pair"),
+    (&[1, 1, 1, 1, 1], "High card"),
+];
 
-        let values = counter.iter().sorted().rev().collect_vec();
-        match values[0] {
-            5 => Hand::Five,
-            4 => Hand::Four,
-            3 => match values[1] {
-                1 => Hand::Three,
-                2 => Hand::FullHouse,
-                _ => unreachable!(),
-            },
-            2 => match values[1] {
-                1 => Hand::Pair,
-                2 => Hand::DoublePair,
-                _ => unreachable!(),
-            },
-            1 => Hand::HighCard,
-            _ => unreachable!(),
+impl Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match HAND_NAMES.iter().find(|(counts, _)| *counts == self.0) {
+            Some((_, name)) => write!(f, "{name}"),
+            None => write!(f, "{}", self.0.iter().join("-")),
         }
     }
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.hand_order().partial_cmp(&other.hand_order())
-    }
-}
+// Either a hand name or a count signature, e.g. "Full house" or "3-2"
+impl FromStr for Hand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((counts, _)) = HAND_NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self(counts.to_vec()));
+        }
+
+        let counts: Vec<usize> = s
+            .split('-')
+            .map(|n| n.trim().parse())
+            .try_collect()
+            .with_context(|| format!("Unknown hand type: {s}"))?;
+        if counts.contains(&0) || counts.windows(2).any(|w| w[0] < w[1]) {
+            bail!("A count signature must be positive and decreasing: {s}")
+        }
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.hand_order().cmp(&other.hand_order())
+        Ok(Self(counts))
     }
 }
 
@@ -244,22 +578,22 @@ mod tests {
     #[test]
     fn str_to_cards() {
         let cases = [
-            ("32T3K", Cards([2, 1, 9, 2, 12]), Hand::Pair),
-            ("T55J5", Cards([9, 4, 4, 10, 4]), Hand::Three),
-            ("KK677", Cards([12, 12, 5, 6, 6]), Hand::DoublePair),
-            ("KTJJT", Cards([12, 9, 10, 10, 9]), Hand::DoublePair),
-            ("QQQJA", Cards([11, 11, 11, 10, 13]), Hand::Three),
-            ("QKJA2", Cards([11, 12, 10, 13, 1]), Hand::HighCard),
-            ("44444", Cards([3, 3, 3, 3, 3]), Hand::Five),
-            ("A2A22", Cards([13, 1, 13, 1, 1]), Hand::FullHouse),
-            ("43444", Cards([3, 2, 3, 3, 3]), Hand::Four),
+            ("32T3K", [1, 0, 8, 1, 11], "One pair"),
+            ("T55J5", [8, 3, 3, 9, 3], "Three of a kind"),
+            ("KK677", [11, 11, 4, 5, 5], "Two pair"),
+            ("KTJJT", [11, 8, 9, 9, 8], "Two pair"),
+            ("QQQJA", [10, 10, 10, 9, 12], "Three of a kind"),
+            ("QKJA2", [10, 11, 9, 12, 0], "High card"),
+            ("44444", [2, 2, 2, 2, 2], "Five of a kind"),
+            ("A2A22", [12, 0, 12, 0, 0], "Full house"),
+            ("43444", [2, 1, 2, 2, 2], "Four of a kind"),
         ];
 
         for (repr, cards, hand) in cases {
-            let hand_cards = Cards::from_str(repr, false).unwrap();
+            let hand_cards = Cards::from_str(repr, STANDARD).unwrap();
             let hand_type = Hand::from_cards(&hand_cards);
-            assert_eq!(hand_cards, cards);
-            assert_eq!(hand_type, hand);
+            assert_eq!(hand_cards, Cards(cards.to_vec()));
+            assert_eq!(hand_type.to_string(), hand);
         }
     }
 
@@ -267,23 +601,23 @@ mod tests {
     fn test_hands() {
         let cases = [
             (
-                Draw::from_str("22345", false).unwrap(),
-                Draw::from_str("23456", false).unwrap(),
+                Draw::from_str("22345", STANDARD).unwrap(),
+                Draw::from_str("23456", STANDARD).unwrap(),
                 Ordering::Greater,
             ),
             (
-                Draw::from_str("22345", false).unwrap(),
-                Draw::from_str("22333", false).unwrap(),
+                Draw::from_str("22345", STANDARD).unwrap(),
+                Draw::from_str("22333", STANDARD).unwrap(),
                 Ordering::Less,
             ),
             (
-                Draw::from_str("22345", false).unwrap(),
-                Draw::from_str("22346", false).unwrap(),
+                Draw::from_str("22345", STANDARD).unwrap(),
+                Draw::from_str("22346", STANDARD).unwrap(),
                 Ordering::Less,
             ),
             (
-                Draw::from_str("22345", false).unwrap(),
-                Draw::from_str("22345", false).unwrap(),
+                Draw::from_str("22345", STANDARD).unwrap(),
+                Draw::from_str("22345", STANDARD).unwrap(),
                 Ordering::Equal,
             ),
         ];
@@ -305,11 +639,390 @@ mod tests {
 
     #[test]
     fn test_2() {
-        assert_eq!(5905, part_2(TEST_1).unwrap());
+        assert_eq!(5905, part_2(TEST_1, WildcardValue::Lowest).unwrap());
     }
 
     #[test]
     fn test_input_2() {
-        assert_eq!(250384185, part_2(INPUT).unwrap());
+        assert_eq!(250384185, part_2(INPUT, WildcardValue::Lowest).unwrap());
+    }
+
+    #[test]
+    fn test_hand_sizes() {
+        let hand = |s| Draw::from_str(s, STANDARD).unwrap().hand;
+
+        // 3 cards
+        assert!(hand("AAA") > hand("KKA"));
+        assert!(hand("KKA") > hand("AKQ"));
+        // 6 cards
+        assert_eq!(Hand(vec![3, 3]), hand("AAAKKK"));
+        assert!(hand("AAAKKK") > hand("AAAKKQ"));
+        assert!(hand("22KKQQ") > hand("AAKQJT"));
+        // 7 cards
+        assert_eq!("4-3", hand("2222333").to_string());
+        assert!(hand("2222333") > hand("AAAAKQJ"));
+        assert!(hand("2223334") > hand("AAAKKQQ"));
+        assert!(hand("2223334") < hand("2222345"));
+        // More than 255 cards of a kind
+        let many = "A".repeat(256);
+        assert_eq!(Hand(vec![256]), hand(&many));
+        assert!(hand(&many) > hand("AAK"));
+        assert_eq!(Hand(vec![300, 1]), "300-1".parse().unwrap());
+
+        assert!(Cards::from_str("", STANDARD).is_err());
+        assert!(Cards::from_str("AB", STANDARD).is_err());
+
+        // Other alphabets
+        let cards = Cards::from_str("cab", "abc").unwrap();
+        assert_eq!(Cards(vec![2, 0, 1]), cards);
+    }
+
+    #[test]
+    fn test_jokers() {
+        let cases = [
+            ("QJJQ2", "Four of a kind"),
+            ("JJJJJ", "Five of a kind"),
+            ("JKKK2", "Four of a kind"),
+            ("T55J5", "Four of a kind"),
+            ("2345J", "One pair"),
+            ("2J4J6", "Three of a kind"),
+        ];
+
+        for (repr, hand) in cases {
+            let draw = Draw::from_str(repr, STANDARD)
+                .unwrap()
+                .resolve_wildcards(&[joker()], WildcardValue::Lowest);
+            assert_eq!(hand, draw.hand.to_string());
+        }
+
+        let draw = Draw::from_str("JJJ2", STANDARD)
+            .unwrap()
+            .resolve_wildcards(&[joker()], WildcardValue::Lowest);
+        assert_eq!(Hand(vec![4]), draw.hand);
+
+        // More than 255 cards, wildcards included
+        let many = "J".repeat(200) + &"A".repeat(100) + "K";
+        let draw = Draw::from_str(&many, STANDARD)
+            .unwrap()
+            .resolve_wildcards(&[joker()], WildcardValue::Lowest);
+        assert_eq!(Hand(vec![300, 1]), draw.hand);
+        let draw = RuleSet::jokers(WildcardValue::Highest)
+            .resolve(&Draw::from_str(&"J".repeat(300), STANDARD).unwrap());
+        assert_eq!(Hand(vec![300]), draw.hand);
+    }
+
+    // Best signature over every way of turning wildcards into a card already in the hand
+    // or into a card that isn't in the hand
+    fn brute_force(cards: &[usize], wildcards: &[usize]) -> Hand {
+        let others = cards
+            .iter()
+            .filter(|c| !wildcards.contains(c))
+            .copied()
+            .collect_vec();
+        let n_wild = cards.len() - others.len();
+        let targets = others
+            .iter()
+            .copied()
+            .unique()
+            .chain((0..n_wild).map(|i| 100 + i))
+            .collect_vec();
+
+        (0..n_wild)
+            .map(|_| targets.iter().copied())
+            .multi_cartesian_product()
+            .map(|assigned| {
+                let all = others.iter().copied().chain(assigned).collect_vec();
+                Hand::from_cards(&Cards(all))
+            })
+            .max()
+            .unwrap_or_else(|| Hand::from_cards(&Cards(cards.to_vec())))
+    }
+
+    #[test]
+    fn test_wildcard_signatures() {
+        let signatures: [&[usize]; 7] = [
+            &[5],
+            &[4, 1],
+            &[3, 2],
+            &[3, 1, 1],
+            &[2, 2, 1],
+            &[2, 1, 1, 1],
+            &[1, 1, 1, 1, 1],
+        ];
+
+        for signature in signatures {
+            // Group i is made of card i
+            let cards = signature
+                .iter()
+                .enumerate()
+                .flat_map(|(i, n)| std::iter::repeat_n(i, *n))
+                .collect_vec();
+            let draw = Draw {
+                hand: Hand::from_cards(&Cards(cards.clone())),
+                cards: Cards(cards.clone()),
+            };
+            assert_eq!(signature, draw.hand.0.as_slice());
+
+            // Every subset of groups can be wildcards
+            for wildcards in (0..signature.len()).powerset() {
+                let resolved = draw.resolve_wildcards(&wildcards, WildcardValue::Natural);
+                assert_eq!(
+                    brute_force(&cards, &wildcards),
+                    resolved.hand,
+                    "{signature:?} with wildcards {wildcards:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_wildcard_values() {
+        let draw = Draw::from_str("KJ2J3", STANDARD).unwrap();
+
+        let cases = [
+            (WildcardValue::Natural, vec![12, 10, 1, 10, 2]),
+            (WildcardValue::Lowest, vec![12, 0, 1, 0, 2]),
+            (
+                WildcardValue::Highest,
+                vec![12, usize::MAX, 1, usize::MAX, 2],
+            ),
+        ];
+        for (value, cards) in cases {
+            let resolved = draw.resolve_wildcards(&[joker()], value);
+            assert_eq!(Hand(vec![3, 1, 1]), resolved.hand);
+            assert_eq!(Cards(cards), resolved.cards);
+        }
+
+        // Several wildcard ranks: 2s and 3s
+        let resolved = draw.resolve_wildcards(&[0, 1], WildcardValue::Natural);
+        assert_eq!(Hand(vec![4, 1]), resolved.hand);
+
+        // Jokers valued lowest in the standard order give the same ranking as the joker order
+        let ranked = |order, wildcards: &[usize], value| {
+            TEST_1
+                .lines()
+                .map(|l| {
+                    let (cards, _) = l.split_once(' ').unwrap();
+                    let draw = Draw::from_str(cards, order).unwrap();
+                    (draw.resolve_wildcards(wildcards, value), cards)
+                })
+                .sorted()
+                .map(|(_, cards)| cards)
+                .collect_vec()
+        };
+        assert_eq!(
+            ranked("J23456789TQKA", &[0], WildcardValue::Natural),
+            ranked(STANDARD, &[joker()], WildcardValue::Lowest)
+        );
+    }
+
+    #[test]
+    fn test_rule_sets() {
+        let jokers = RuleSet::from_toml(
+            r#"
+            wildcards = "J"
+            wildcard_value = "lowest"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(5905, winnings(TEST_1, &jokers).unwrap());
+        assert_eq!(
+            6440,
+            winnings(TEST_1, &RuleSet::from_toml("").unwrap()).unwrap()
+        );
+
+        // Full houses beat four of a kind
+        let input = "22223 10\n33322 1";
+        let precedence = RuleSet::from_toml(
+            r#"precedence = ["5", "Full house", "4-1", "3-1-1", "2-2-1", "2-1-1-1", "1-1-1-1-1"]"#,
+        )
+        .unwrap();
+        assert_eq!(21, winnings(input, &RuleSet::standard()).unwrap());
+        assert_eq!(12, winnings(input, &precedence).unwrap());
+
+        // Hand types left out of the precedence can't be ranked
+        let partial = RuleSet::from_toml(r#"precedence = ["5", "4-1"]"#).unwrap();
+        assert!(winnings(input, &partial).is_err());
+
+        let alphabet = RuleSet::from_toml(r#"order = "abc""#).unwrap();
+        assert_eq!(14, winnings("abc 1\ncab 2\naab 3", &alphabet).unwrap());
+        assert!(winnings(TEST_1, &alphabet).is_err());
+
+        let invalid = [
+            r#"order = """#,
+            r#"order = "abca""#,
+            r#"wildcards = "X""#,
+            r#"wildcard_value = "wild""#,
+            r#"precedence = ["1-2"]"#,
+            r#"precedence = ["5", "Five of a kind"]"#,
+            r#"jokers = "J""#,
+        ];
+        for config in invalid {
+            assert!(RuleSet::from_toml(config).is_err(), "{config}");
+        }
+    }
+
+    #[test]
+    fn str_to_hand() {
+        assert_eq!(Hand(vec![3, 2]), "Full house".parse().unwrap());
+        assert_eq!(Hand(vec![3, 2]), "3-2".parse().unwrap());
+        assert_eq!(Hand(vec![4, 3]), "4-3".parse().unwrap());
+        assert!("0-5".parse::<Hand>().is_err());
+        assert!("Royal flush".parse::<Hand>().is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let explanations = explain(TEST_1, &RuleSet::jokers(WildcardValue::Lowest)).unwrap();
+        let summary = explanations
+            .iter()
+            .map(|e| {
+                (
+                    e.cards.as_str(),
+                    e.detected.to_string(),
+                    e.resolved.to_string(),
+                    e.bid * e.rank,
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            vec![
+                ("32T3K", "One pair".to_string(), "One pair".to_string(), 765),
+                ("KK677", "Two pair".to_string(), "Two pair".to_string(), 56),
+                (
+                    "T55J5",
+                    "Three of a kind".to_string(),
+                    "Four of a kind".to_string(),
+                    2052
+                ),
+                (
+                    "QQQJA",
+                    "Three of a kind".to_string(),
+                    "Four of a kind".to_string(),
+                    1932
+                ),
+                (
+                    "KTJJT",
+                    "Two pair".to_string(),
+                    "Four of a kind".to_string(),
+                    1100
+                ),
+            ],
+            summary
+        );
+        assert_eq!(
+            5905,
+            explanations.iter().map(|e| e.bid * e.rank).sum::<usize>()
+        );
+
+        assert_eq!(Decision::Weakest, explanations[0].decision);
+        assert_eq!(
+            Decision::HandType {
+                below: "32T3K".to_string()
+            },
+            explanations[1].decision
+        );
+        assert_eq!(
+            Decision::Card {
+                below: "T55J5".to_string(),
+                position: 0,
+                ours: 'Q',
+                theirs: 'T'
+            },
+            explanations[3].decision
+        );
+        assert_eq!(
+            "beats QQQJA on card 1: K > Q",
+            explanations[4].decision.to_string()
+        );
+        assert!(explanations.iter().all(|e| e.duplicate_of.is_none()));
+    }
+
+    #[test]
+    fn test_explain_ties() {
+        let input = "KK677 1\nQQ234 2\nKK677 3\nKK677 4";
+        let explanations = explain(input, &RuleSet::standard()).unwrap();
+        let ranked = explanations
+            .iter()
+            .map(|e| (e.bid, e.duplicate_of))
+            .collect_vec();
+        // Identical hands keep their input order
+        assert_eq!(
+            vec![(2, None), (1, None), (3, Some(2)), (4, Some(2))],
+            ranked
+        );
+        assert_eq!(
+            Decision::Tie {
+                below: "KK677".to_string()
+            },
+            explanations[2].decision
+        );
+        assert!(explanations[3]
+            .to_string()
+            .ends_with("ties with KK677, kept in input order (duplicate of rank 2)"));
+
+        // Different wildcards valued the same way tie without being duplicates
+        let rules = RuleSet::from_toml(r#"wildcards = "JQ""#).unwrap();
+        let explanations = explain("Q2345 1\nJ2345 2", &rules).unwrap();
+        assert_eq!(
+            Decision::Tie {
+                below: "Q2345".to_string()
+            },
+            explanations[1].decision
+        );
+        assert_eq!(None, explanations[1].duplicate_of);
+    }
+
+    #[test]
+    fn test_parse_hands() {
+        let hands = parse_hands(TEST_1, STANDARD).unwrap();
+        assert_eq!(5, hands.len());
+        assert_eq!((Draw::from_str("32T3K", STANDARD).unwrap(), 765), hands[0]);
+        assert_eq!("QQQJA", hands[4].0.cards.repr(STANDARD));
+
+        // Trailing whitespace and CRLF
+        let hands = parse_hands("32T3K 765  \r\nT55J5 684\n\n", STANDARD).unwrap();
+        assert_eq!(
+            vec![765, 684],
+            hands.iter().map(|(_, bid)| *bid).collect_vec()
+        );
+
+        assert!(parse_hands("", STANDARD).is_err());
+        assert!(parse_hands("32T3K", STANDARD).is_err());
+        assert!(parse_hands("32T3K -5", STANDARD).is_err());
+        assert!(parse_hands("32T3K 765\nT55J5", STANDARD).is_err());
+        assert!(parse_hands("32T3K 99999999999999999999999", STANDARD).is_err());
+
+        let error = parse_hands("32T3K 765\nT55X5 684", STANDARD).unwrap_err();
+        assert_eq!("Invalid hand on line 2: T55X5", error.to_string());
+    }
+
+    #[test]
+    fn test_large_orders() {
+        let card = |i: u32| char::from_u32(0x100 + i).unwrap();
+        let order: String = (0..300).map(card).collect();
+
+        // Ranks past 255 stay distinct
+        let low = Cards::from_str(&card(0).to_string(), &order).unwrap();
+        let high = Cards::from_str(&card(256).to_string(), &order).unwrap();
+        assert!(low < high);
+
+        let wild = card(255);
+        for value in ["lowest", "natural", "highest"] {
+            let rules = RuleSet::from_toml(&format!(
+                "order = \"{order}\"\nwildcards = \"{wild}\"\nwildcard_value = \"{value}\""
+            ))
+            .unwrap();
+            let input = format!(
+                "{}{}{wild} 1\n{}{}{} 10",
+                card(299),
+                card(254),
+                card(1),
+                card(1),
+                card(2)
+            );
+            // The wildcard pairs up with the highest card, which then wins the tie-break
+            assert_eq!(2 + 10, winnings(&input, &rules).unwrap());
+        }
     }
 }